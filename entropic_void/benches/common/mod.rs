@@ -0,0 +1,26 @@
+/*
+Fixtures shared by the bench targets.
+With the parallel feature, each bench has a single_thread variant that runs the same work in
+single_thread_pool for the serial comparison.
+*/
+
+use entropic_void::lattice::Lattice;
+
+/*
+An n³ lattice with a deterministic, non-uniform e[0][0] field.
+*/
+pub fn lattice(n: usize) -> Lattice {
+    let mut lattice = Lattice::new((n, n, n)).unwrap();
+    for (c, cell) in lattice.iter_cells_mut() {
+        cell.e[0][0] = 1.0 + ((c.x * 7 + c.y * 3 + c.z * 11) % 13) as f64 / 13.0;
+    }
+    lattice
+}
+
+/*
+A one-thread rayon pool.
+*/
+#[cfg(feature = "parallel")]
+pub fn single_thread_pool() -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap()
+}
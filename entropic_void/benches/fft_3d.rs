@@ -0,0 +1,26 @@
+#![feature(test)]
+
+/*
+Timing for utils::fft_3d; run with cargo bench --bench fft_3d.
+*/
+
+extern crate test;
+
+mod common;
+
+use entropic_void::utils;
+use test::Bencher;
+
+#[bench]
+fn fft_3d_32(b: &mut Bencher) {
+    let lattice = common::lattice(32);
+    b.iter(|| utils::fft_3d(test::black_box(&lattice), 0, 0));
+}
+
+#[cfg(feature = "parallel")]
+#[bench]
+fn fft_3d_32_single_thread(b: &mut Bencher) {
+    let lattice = common::lattice(32);
+    let pool = common::single_thread_pool();
+    b.iter(|| pool.install(|| utils::fft_3d(test::black_box(&lattice), 0, 0)));
+}
@@ -0,0 +1,26 @@
+#![feature(test)]
+
+/*
+Timing for conservation::compute_pattern_metrics; run with cargo bench --bench pattern_metrics.
+*/
+
+extern crate test;
+
+mod common;
+
+use entropic_void::conservation;
+use test::Bencher;
+
+#[bench]
+fn pattern_metrics_32(b: &mut Bencher) {
+    let lattice = common::lattice(32);
+    b.iter(|| conservation::compute_pattern_metrics(test::black_box(&lattice)));
+}
+
+#[cfg(feature = "parallel")]
+#[bench]
+fn pattern_metrics_32_single_thread(b: &mut Bencher) {
+    let lattice = common::lattice(32);
+    let pool = common::single_thread_pool();
+    b.iter(|| pool.install(|| conservation::compute_pattern_metrics(test::black_box(&lattice))));
+}
@@ -0,0 +1,238 @@
+#![forbid(unsafe_code)]
+
+/*
+Purpose: Describe a simulation in a JSON file instead of Rust code (config feature).
+
+Uses Lattice, ConstraintSet, RedistributionMatrix from types and lattice.
+Uses init::initialize_homogeneous to fill the lattice.
+Builds: evolution::Simulation, via Config::build or Simulation::from_config_file.
+*/
+
+use std::path::Path;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use crate::evolution::{MAX_COUPLING, Simulation};
+use crate::init::{self, EnergyDistribution};
+use crate::lattice::Lattice;
+use crate::types::{ConstraintSet, FORCES, N_FLATTENED, RedistributionMatrix, VARS, VariableConstraint};
+use crate::utils::SimRng;
+
+/*
+One non-zero redistribution entry, a[from][to] = rate, as in RedistributionMatrix::to_sparse_string.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RedistributionEntry {
+    pub from: usize,
+    pub to: usize,
+    pub rate: f64,
+}
+
+/*
+Everything needed to build a Simulation.
+The lattice starts homogeneous: total_energy spread evenly over cells and channels, each cell perturbed
+by up to ±noise_fraction (drawn from seed), then projected onto the constraints.
+Omitted fields after total_energy default to no noise, seed 0, no redistribution, no coupling, free constraints.
+*/
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub size: (usize, usize, usize),
+    pub total_energy: f64,
+    #[serde(default)]
+    pub noise_fraction: f64,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub redistribution: Vec<RedistributionEntry>,
+    #[serde(default)]
+    pub coupling: [[f64; FORCES]; VARS],
+    #[serde(default)]
+    pub constraints: ConstraintSet,
+}
+
+/**/
+impl Config {
+    /*
+    Parses and validates a JSON config.
+    */
+    pub fn from_json(text: &str) -> Result<Config, String> {
+        let config: Config = serde_json::from_str(text).map_err(|e| format!("invalid config: {e}"))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /*
+    Reads path and parses it like from_json; errors name the file.
+    */
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Config, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        Config::from_json(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /*
+    Pretty-printed JSON that from_json reads back to an equal Config.
+    Fails on non-finite numbers, which JSON cannot hold.
+    */
+    pub fn to_json(&self) -> Result<String, String> {
+        self.validate()?;
+        serde_json::to_string_pretty(self).map_err(|e| format!("cannot serialize config: {e}"))
+    }
+
+    /*
+    Checks:
+        every size dimension is non-zero;
+        total_energy is finite and non-negative, noise_fraction in [0, 1];
+        redistribution entries are in 0..N_FLATTENED, finite, and each (from, to) appears once;
+        coupling entries are in [0, MAX_COUPLING];
+        FixedTotal / FixedRatio values are finite and non-negative,
+        and locked expression rows are finite, non-negative, and sum to 1 ± init::DISTRIBUTION_TOLERANCE.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        let (sx, sy, sz) = self.size;
+        if sx == 0 || sy == 0 || sz == 0 {
+            return Err(format!("size ({sx}, {sy}, {sz}) has a zero dimension"));
+        }
+        if !self.total_energy.is_finite() || self.total_energy < 0.0 {
+            return Err(format!("total_energy = {} must be finite and non-negative", self.total_energy));
+        }
+        if !(0.0..=1.0).contains(&self.noise_fraction) {
+            return Err(format!("noise_fraction = {} must be in [0, 1]", self.noise_fraction));
+        }
+        let mut seen = [[false; N_FLATTENED]; N_FLATTENED];
+        for (n, entry) in self.redistribution.iter().enumerate() {
+            if entry.from >= N_FLATTENED || entry.to >= N_FLATTENED {
+                return Err(format!(
+                    "redistribution[{n}]: ({}, {}) is outside 0..{N_FLATTENED}",
+                    entry.from, entry.to
+                ));
+            }
+            if !entry.rate.is_finite() {
+                return Err(format!("redistribution[{n}]: rate {} is not finite", entry.rate));
+            }
+            if std::mem::replace(&mut seen[entry.from][entry.to], true) {
+                return Err(format!("redistribution[{n}]: entry ({}, {}) given twice", entry.from, entry.to));
+            }
+        }
+        for (i, row) in self.coupling.iter().enumerate() {
+            for (f, &c) in row.iter().enumerate() {
+                if !(0.0..=MAX_COUPLING).contains(&c) {
+                    return Err(format!("coupling[{i}][{f}] = {c} must be in [0, {MAX_COUPLING}]"));
+                }
+            }
+        }
+        let non_negative = |v: f64| v.is_finite() && v >= 0.0;
+        for (i, constraint) in self.constraints.var_constraints.iter().enumerate() {
+            match constraint {
+                VariableConstraint::Free => {}
+                VariableConstraint::FixedTotal(t) if !non_negative(*t) => {
+                    return Err(format!("var_constraints[{i}]: FixedTotal({t}) must be finite and non-negative"));
+                }
+                VariableConstraint::FixedRatio(ratios) if !ratios.iter().all(|&r| non_negative(r)) => {
+                    return Err(format!("var_constraints[{i}]: FixedRatio{ratios:?} must be finite and non-negative"));
+                }
+                _ => {}
+            }
+        }
+        for (i, expr) in self.constraints.expr_constraints.iter().enumerate() {
+            if !expr.locked {
+                continue;
+            }
+            let sum: f64 = expr.force_pct.iter().sum();
+            if !expr.force_pct.iter().all(|&p| non_negative(p)) || (sum - 1.0).abs() > init::DISTRIBUTION_TOLERANCE {
+                return Err(format!(
+                    "expr_constraints[{i}]: locked force_pct {:?} must be non-negative and sum to 1",
+                    expr.force_pct
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /**/
+    pub fn redistribution_matrix(&self) -> RedistributionMatrix {
+        let mut matrix = RedistributionMatrix::default();
+        for entry in &self.redistribution {
+            matrix.a[entry.from][entry.to] = entry.rate;
+        }
+        matrix
+    }
+
+    /*
+    Validates, fills the lattice as described on Config, and builds the Simulation.
+    The same config always builds the same Simulation (see utils::SimRng for across-build guarantees).
+    */
+    pub fn build(&self) -> Result<Simulation, String> {
+        self.validate()?;
+        let mut lattice = Lattice::new(self.size).ok_or("lattice size must be non-zero")?;
+        let per_cell = self.total_energy / lattice.cells().len() as f64;
+        let even = EnergyDistribution { total: 1.0, ..EnergyDistribution::default() };
+        let mut rng = SimRng::seed_from_u64(self.seed);
+        init::initialize_homogeneous(&mut lattice, per_cell, self.noise_fraction, &even, &self.constraints, &mut rng)?;
+        Ok(Simulation::new(lattice, self.redistribution_matrix(), self.coupling, self.constraints.clone())?)
+    }
+}
+
+/**/
+impl Simulation {
+    /*
+    Config::from_file(path)?.build().
+    */
+    pub fn from_config_file(path: impl AsRef<Path>) -> Result<Simulation, String> {
+        Config::from_file(path)?.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ExpressionConstraint;
+
+    fn built_config() -> Config {
+        let mut constraints = ConstraintSet::default();
+        constraints.var_constraints[1] = VariableConstraint::FixedRatio([1.0, 2.0, 0.0, 0.0, 1.0]);
+        constraints.expr_constraints[0] = ExpressionConstraint { locked: true, force_pct: [0.5, 0.25, 0.25, 0.0] };
+        Config {
+            size: (4, 3, 2),
+            total_energy: 240.0,
+            noise_fraction: 0.1,
+            seed: 9,
+            redistribution: vec![
+                RedistributionEntry { from: 0, to: 5, rate: 0.3 },
+                RedistributionEntry { from: 5, to: 0, rate: -0.3 },
+            ],
+            coupling: [[0.1; FORCES]; VARS],
+            constraints,
+        }
+    }
+
+    #[test]
+    fn config_round_trips_into_the_same_simulation() {
+        let config = built_config();
+        let json = config.to_json().unwrap();
+        assert_eq!(Config::from_json(&json).unwrap(), config);
+        let path = std::env::temp_dir().join(format!("entropic_void_{}_config.json", std::process::id()));
+        std::fs::write(&path, &json).unwrap();
+        let loaded = Simulation::from_config_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        let (built, loaded) = (config.build().unwrap(), loaded.unwrap());
+        assert_eq!(built.lattice.cells(), loaded.lattice.cells());
+        assert_eq!(built.redistribution.a, loaded.redistribution.a);
+        assert_eq!(built.coupling, loaded.coupling);
+        assert_eq!(built.constraints, loaded.constraints);
+    }
+
+    #[test]
+    fn minimal_configs_default_and_bad_ones_are_rejected() {
+        let minimal = Config::from_json(r#"{"size":[2,2,2],"total_energy":8.0}"#).unwrap().build().unwrap();
+        assert!((minimal.lattice.cells()[0].e[0][0] - 0.05).abs() < 1e-15);
+        for bad in [
+            r#"{"size":[0,2,2],"total_energy":8.0}"#,
+            r#"{"size":[2,2],"total_energy":8.0}"#,
+            r#"{"size":[2,2,2],"total_energy":8.0,"redistribution":[{"from":20,"to":0,"rate":1.0}]}"#,
+            r#"{"size":[2,2,2],"total_energy":8.0,"coupling":[[-1,0,0,0],[0,0,0,0],[0,0,0,0],[0,0,0,0],[0,0,0,0]]}"#,
+        ] {
+            assert!(Config::from_json(bad).is_err(), "{bad}");
+        }
+        assert!(Simulation::from_config_file("/nonexistent/config.json").is_err());
+    }
+}
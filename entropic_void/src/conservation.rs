@@ -5,11 +5,15 @@ Uses Lattice, CellState, ConstraintSet, OscillationMode, PatternMetrics from typ
 Uses energy aggregation helpers.
 Often used by evolution::verify_energy_conservation and tests.
 */
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use crate::energy;
 use crate::lattice::Lattice;
-use crate::types::{CellState, ConstraintSet, LatticeCoord, OscillationMode, FORCES, VARS};
+use crate::types::{CellState, ConstraintSet, Direction, LatticeCoord, OscillationMode, VariableConstraint, FORCES, VARS};
+use crate::visualization;
 
 /**/
-#[derive(Default)]
+#[derive(Default, Clone, Debug)]
 pub struct PatternMetrics {
     pub total_energy: f64,
     pub variance: f64,
@@ -22,6 +26,43 @@ pub struct PatternMetrics {
     pub void_wall_filament_ratio: (f64, f64, f64),
 }
 
+/**/
+impl PatternMetrics {
+    /*
+    Every field within tol (absolute) of other's; NaN never matches.
+    */
+    pub fn approx_eq(&self, other: &PatternMetrics, tol: f64) -> bool {
+        self.diff(other, tol).is_empty()
+    }
+
+    /*
+    One "field: self vs other (Δ difference)" line per field differing by more than tol, for test failure messages.
+    */
+    pub fn diff(&self, other: &PatternMetrics, tol: f64) -> Vec<String> {
+        let fields = [
+            ("total_energy", self.total_energy, other.total_energy),
+            ("variance", self.variance, other.variance),
+            ("skewness", self.skewness, other.skewness),
+            ("kurtosis", self.kurtosis, other.kurtosis),
+            ("local_clustering", self.local_clustering, other.local_clustering),
+            ("fractal_dimension", self.fractal_dimension, other.fractal_dimension),
+            ("void_fraction", self.void_fraction, other.void_fraction),
+            ("filament_fraction", self.filament_fraction, other.filament_fraction),
+            ("void_wall_filament_ratio.0", self.void_wall_filament_ratio.0, other.void_wall_filament_ratio.0),
+            ("void_wall_filament_ratio.1", self.void_wall_filament_ratio.1, other.void_wall_filament_ratio.1),
+            ("void_wall_filament_ratio.2", self.void_wall_filament_ratio.2, other.void_wall_filament_ratio.2),
+        ];
+        fields
+            .iter()
+            .filter(|(_, a, b)| {
+                let d = (a - b).abs();
+                d.is_nan() || d > tol
+            })
+            .map(|(name, a, b)| format!("{name}: {a:?} vs {b:?} (Δ {:?})", a - b))
+            .collect()
+    }
+}
+
 /**/
 #[derive(Default)]
 pub struct ConservationReport {
@@ -31,12 +72,361 @@ pub struct ConservationReport {
     pub constraint_violations: Vec<String>,
 }
 
+/*
+How void/wall/filament thresholds on total cell density are chosen.
+Cells below the low threshold are voids, above the high one filaments, the rest walls.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClassificationPolicy {
+    // mean ± k·σ.
+    Sigma(f64),
+    // Lowest and highest fractions of cells, e.g. (0.2, 0.8): the bottom 20% are voids, the top 20% filaments.
+    // Exact for distinct densities; ties at a cut fall into walls.
+    Percentile(f64, f64),
+    // Fixed (low, high) densities.
+    Absolute(f64, f64),
+}
+
+/**/
+impl Default for ClassificationPolicy {
+    fn default() -> Self {
+        ClassificationPolicy::Sigma(1.0)
+    }
+}
+
+/*
+Lattice-wide energy totals; the global counterpart of energy::per_variable / per_force.
+*/
+#[derive(Default, Clone, Debug)]
+pub struct EnergyBudget {
+    pub total: f64,
+    pub per_variable: [f64; VARS],
+    pub per_force: [f64; FORCES],
+    pub per_channel: [[f64; FORCES]; VARS],
+}
+
+/*
+Connected above-threshold region found by find_peaks.
+*/
+#[derive(Default, Clone, Debug)]
+pub struct Halo {
+    pub cells: Vec<LatticeCoord>,
+    pub total_energy: f64,
+    // Cell with the highest total energy.
+    pub peak: LatticeCoord,
+}
+
+/*
+Compact multi-line summary: global error, the worst variable and force with their errors,
+and one line per constraint violation.
+*/
+impl std::fmt::Display for ConservationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let worst = |errors: &[f64]| {
+            errors
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap_or((0, 0.0))
+        };
+        let (var, var_error) = worst(&self.per_variable_error);
+        let (force, force_error) = worst(&self.per_force_error);
+        writeln!(f, "global energy error:  {:.3e}", self.global_energy_error)?;
+        writeln!(f, "worst variable error: {var_error:.3e} (var {var})")?;
+        writeln!(f, "worst force error:    {force_error:.3e} (force {force})")?;
+        if self.constraint_violations.is_empty() {
+            write!(f, "constraint violations: none")
+        } else {
+            write!(f, "constraint violations: {}", self.constraint_violations.len())?;
+            for violation in &self.constraint_violations {
+                write!(f, "\n  - {violation}")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/*
+Single-pass mean/variance/min/max (plus third and fourth central moments) via Welford's update.
+Partial accumulators from disjoint chunks combine exactly with merge,
+so the same numbers come out of a serial or a rayon fold/reduce pass.
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct DensityAccumulator {
+    count: u64,
+    sum: f64,
+    mean: f64,
+    m2: f64,
+    m3: f64,
+    m4: f64,
+    min: f64,
+    max: f64,
+}
+
+/**/
+impl Default for DensityAccumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/**/
+impl DensityAccumulator {
+    /**/
+    pub fn new() -> DensityAccumulator {
+        DensityAccumulator {
+            count: 0,
+            sum: 0.0,
+            mean: 0.0,
+            m2: 0.0,
+            m3: 0.0,
+            m4: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /*
+    One pass over per-cell total energy, parallel with the parallel feature.
+    */
+    pub fn from_lattice(lattice: &Lattice) -> DensityAccumulator {
+        for_chunks(lattice.cells(), |_, chunk| {
+            chunk.iter().fold(DensityAccumulator::new(), |acc, cell| acc.push(energy::total_energy(cell)))
+        })
+        .into_iter()
+        .fold(DensityAccumulator::new(), DensityAccumulator::merge)
+    }
+
+    /*
+    As from_lattice, over precomputed values.
+    */
+    pub fn from_values(values: &[f64]) -> DensityAccumulator {
+        for_chunks(values, |_, chunk| chunk.iter().fold(DensityAccumulator::new(), |acc, &x| acc.push(x)))
+            .into_iter()
+            .fold(DensityAccumulator::new(), DensityAccumulator::merge)
+    }
+
+    /**/
+    pub fn push(mut self, x: f64) -> DensityAccumulator {
+        let n1 = self.count as f64;
+        self.count += 1;
+        let n = self.count as f64;
+        let delta = x - self.mean;
+        let delta_n = delta / n;
+        let delta_n2 = delta_n * delta_n;
+        let term1 = delta * delta_n * n1;
+        self.mean += delta_n;
+        self.m4 += term1 * delta_n2 * (n * n - 3.0 * n + 3.0) + 6.0 * delta_n2 * self.m2 - 4.0 * delta_n * self.m3;
+        self.m3 += term1 * delta_n * (n - 2.0) - 3.0 * delta_n * self.m2;
+        self.m2 += term1;
+        self.sum += x;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        self
+    }
+
+    /*
+    Combine accumulators over disjoint samples (Chan/Pébay pairwise formulas).
+    */
+    pub fn merge(self, other: DensityAccumulator) -> DensityAccumulator {
+        if self.count == 0 {
+            return other;
+        }
+        if other.count == 0 {
+            return self;
+        }
+        let na = self.count as f64;
+        let nb = other.count as f64;
+        let n = na + nb;
+        let delta = other.mean - self.mean;
+        let delta2 = delta * delta;
+        let m2 = self.m2 + other.m2 + delta2 * na * nb / n;
+        let m3 = self.m3 + other.m3
+            + delta2 * delta * na * nb * (na - nb) / (n * n)
+            + 3.0 * delta * (na * other.m2 - nb * self.m2) / n;
+        let m4 = self.m4 + other.m4
+            + delta2 * delta2 * na * nb * (na * na - na * nb + nb * nb) / (n * n * n)
+            + 6.0 * delta2 * (na * na * other.m2 + nb * nb * self.m2) / (n * n)
+            + 4.0 * delta * (na * other.m3 - nb * self.m3) / n;
+        DensityAccumulator {
+            count: self.count + other.count,
+            sum: self.sum + other.sum,
+            mean: self.mean + delta * nb / n,
+            m2,
+            m3,
+            m4,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    /**/
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /**/
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /**/
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /*
+    Population variance; 0.0 when empty.
+    */
+    pub fn variance(&self) -> f64 {
+        if self.count == 0 { 0.0 } else { self.m2 / self.count as f64 }
+    }
+
+    /*
+    0.0 for a constant field.
+    */
+    pub fn skewness(&self) -> f64 {
+        if self.m2 <= 0.0 { 0.0 } else { (self.count as f64).sqrt() * self.m3 / self.m2.powf(1.5) }
+    }
+
+    /*
+    Excess kurtosis; 0.0 for a constant field.
+    */
+    pub fn kurtosis(&self) -> f64 {
+        if self.m2 <= 0.0 { 0.0 } else { self.count as f64 * self.m4 / (self.m2 * self.m2) - 3.0 }
+    }
+
+    /*
+    +inf when empty.
+    */
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    /*
+    -inf when empty.
+    */
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+}
+
+/*
+Items per chunk in the lattice-wide passes below.
+*/
+const CHUNK: usize = 4096;
+
+/*
+f(start index, chunk) over fixed CHUNK-sized slices, results in chunk order.
+The chunking does not depend on threads, so merging the results in order gives the same bits whether
+the chunks ran in parallel (parallel feature) or serially.
+*/
+fn for_chunks<T: Sync, R: Send>(items: &[T], f: impl Fn(usize, &[T]) -> R + Sync) -> Vec<R> {
+    #[cfg(feature = "parallel")]
+    let chunks = items.par_chunks(CHUNK);
+    #[cfg(not(feature = "parallel"))]
+    let chunks = items.chunks(CHUNK);
+    chunks.enumerate().map(|(k, chunk)| f(k * CHUNK, chunk)).collect()
+}
+
 /*
 Uses energy::total_energy for each cell.
 Returns relative error.
+The total is total_energy_stable, so the result does not depend on the thread count.
 */
 pub fn verify_global_conservation(lattice: &Lattice, initial_energy: f64) -> f64 {
-    todo!();
+    relative_error(total_energy_stable(lattice), initial_energy)
+}
+
+/*
+Relative error of the lattice total against expected_total(time), for runs that are meant to gain or lose energy
+(expansion / dilution, sources): only the drift away from the intended total is reported.
+With a constant expected_total this is verify_global_conservation.
+*/
+pub fn verify_conservation_with_expectation(lattice: &Lattice, time: f64, expected_total: impl Fn(f64) -> f64) -> f64 {
+    relative_error(total_energy_stable(lattice), expected_total(time))
+}
+
+/*
+Ok when verify_global_conservation(lattice, initial) <= rel_tol; otherwise an error giving the actual and initial totals,
+the relative error, and the tolerance. For CI checks and user code alike; never panics.
+*/
+pub fn assert_conserved(lattice: &Lattice, initial: f64, rel_tol: f64) -> Result<(), String> {
+    let total = total_energy_stable(lattice);
+    let error = relative_error(total, initial);
+    if error.is_nan() || error > rel_tol {
+        return Err(format!(
+            "energy not conserved: total {total:e} vs initial {initial:e}, relative error {error:.3e} exceeds tolerance {rel_tol:e}"
+        ));
+    }
+    Ok(())
+}
+
+/*
+Sum of energy::total_energy over all cells, bit-identical for any thread count:
+compensated (Neumaier) sums over fixed for_chunks chunks, merged in chunk order with the same compensation.
+Also more accurate than a plain sum on large lattices.
+*/
+pub fn total_energy_stable(lattice: &Lattice) -> f64 {
+    let parts = for_chunks(lattice.cells(), |_, chunk| {
+        let mut sum = CompensatedSum::default();
+        for cell in chunk {
+            sum.add(energy::total_energy(cell));
+        }
+        sum
+    });
+    let mut total = CompensatedSum::default();
+    for part in parts {
+        total.add(part.sum);
+        total.compensation += part.compensation;
+    }
+    total.value()
+}
+
+#[derive(Default)]
+struct CompensatedSum {
+    sum: f64,
+    compensation: f64,
+}
+
+impl CompensatedSum {
+    fn add(&mut self, x: f64) {
+        let t = self.sum + x;
+        self.compensation += if self.sum.abs() >= x.abs() { (self.sum - t) + x } else { (x - t) + self.sum };
+        self.sum = t;
+    }
+
+    fn value(&self) -> f64 {
+        self.sum + self.compensation
+    }
+}
+
+/*
+Sums every channel across all cells, then derives the variable, force, and grand totals from those sums.
+*/
+pub fn energy_budget(lattice: &Lattice) -> EnergyBudget {
+    let mut budget = EnergyBudget::default();
+    for cell in lattice.cells() {
+        for (total_row, row) in budget.per_channel.iter_mut().zip(cell.e.iter()) {
+            for (total, value) in total_row.iter_mut().zip(row.iter()) {
+                *total += value;
+            }
+        }
+    }
+    let channels = CellState { e: budget.per_channel };
+    budget.per_variable = energy::per_variable(&channels);
+    budget.per_force = energy::per_force(&channels);
+    budget.total = energy::total_energy(&channels);
+    budget
+}
+
+/*
+|actual - expected| / |expected|, or the absolute error when expected is 0.
+*/
+fn relative_error(actual: f64, expected: f64) -> f64 {
+    let diff = (actual - expected).abs();
+    if expected == 0.0 { diff } else { diff / expected.abs() }
 }
 
 /**/
@@ -49,28 +439,404 @@ Checks:
     If FixedTotal, sums match.
     If ExpressionConstraint.locked, percentages match.
 Collects violations.
+Each violation is constraint_violation_cells' reason prefixed with the cell coordinate, at tolerance 1e-9.
+The error fields need a reference state, so they are left at zero; see verify_variable_conservation.
 */
 pub fn verify_constraints(lattice: &Lattice, constraints: &ConstraintSet) -> ConservationReport {
-    todo!();
+    ConservationReport {
+        constraint_violations: constraint_violation_cells(lattice, constraints, 1e-9)
+            .into_iter()
+            .map(|(c, reason)| format!("({}, {}, {}): {reason}", c.x, c.y, c.z))
+            .collect(),
+        ..ConservationReport::default()
+    }
+}
+
+/*
+Every cell breaking a constraint, with the reason, in lattice order (one entry per broken constraint):
+    FixedTotal(t): the variable's row sum differs from t.
+    FixedRatio(r): the row sum differs from r[i] / Σr of the cell total.
+    locked ExpressionConstraint: some e[i][f] differs from row sum · force_pct[f].
+Differences are relative to the expected value (absolute when it is 0) and must exceed tol.
+*/
+pub fn constraint_violation_cells(lattice: &Lattice, constraints: &ConstraintSet, tol: f64) -> Vec<(LatticeCoord, String)> {
+    let mut violations = vec![];
+    for (coord, cell) in lattice.iter_cells() {
+        let cell_total = energy::total_energy(cell);
+        for (i, row) in cell.e.iter().enumerate() {
+            let row_total: f64 = row.iter().sum();
+            let expected = match &constraints.var_constraints[i] {
+                VariableConstraint::Free => None,
+                VariableConstraint::FixedTotal(t) => Some(*t),
+                VariableConstraint::FixedRatio(ratios) => {
+                    let sum: f64 = ratios.iter().sum();
+                    (sum > 0.0).then(|| cell_total * ratios[i] / sum)
+                }
+            };
+            if let Some(expected) = expected.filter(|&e| relative_error(row_total, e) > tol) {
+                violations.push((coord, format!("var {i} total {row_total:.6e}, constraint requires {expected:.6e}")));
+            }
+            let expr = &constraints.expr_constraints[i];
+            if !expr.locked {
+                continue;
+            }
+            for (f, (&value, pct)) in row.iter().zip(expr.force_pct.iter()).enumerate() {
+                let expected = row_total * pct;
+                if relative_error(value, expected) > tol {
+                    violations.push((coord, format!("var {i} force {f} is {value:.6e}, locked expression requires {expected:.6e}")));
+                }
+            }
+        }
+    }
+    violations
+}
+
+/*
+Energy-weighted mean position, averaged on the torus: each axis maps to an angle 2π·c/n,
+and the weighted circular mean is mapped back to [0, n).
+Negative cell energies count as zero weight; with no positive energy the result is the origin.
+*/
+pub fn center_of_mass(lattice: &Lattice) -> (f64, f64, f64) {
+    let (sx, sy, sz) = lattice.size();
+    let mut sums = [(0.0, 0.0); 3];
+    for (coord, cell) in lattice.iter_cells() {
+        let w = energy::total_energy(cell).max(0.0);
+        for (sum, (c, n)) in sums.iter_mut().zip([(coord.x, sx), (coord.y, sy), (coord.z, sz)]) {
+            let angle = std::f64::consts::TAU * c as f64 / n as f64;
+            sum.0 += w * angle.cos();
+            sum.1 += w * angle.sin();
+        }
+    }
+    let axis = |(cos, sin): (f64, f64), n: usize| {
+        if cos == 0.0 && sin == 0.0 {
+            return 0.0;
+        }
+        let c = f64::atan2(sin, cos).rem_euclid(std::f64::consts::TAU) * n as f64 / std::f64::consts::TAU;
+        // rem_euclid can round a tiny negative angle up to exactly TAU.
+        if c >= n as f64 { 0.0 } else { c }
+    };
+    (axis(sums[0], sx), axis(sums[1], sy), axis(sums[2], sz))
+}
+
+/*
+Energy-weighted RMS distance from center_of_mass, using minimum-image (periodic) distances.
+0.0 with no positive energy.
+*/
+pub fn rms_spread(lattice: &Lattice) -> f64 {
+    let (sx, sy, sz) = lattice.size();
+    let center = center_of_mass(lattice);
+    let image = |c: usize, m: f64, n: usize| {
+        let d = (c as f64 - m).rem_euclid(n as f64);
+        d.min(n as f64 - d)
+    };
+    let mut weight = 0.0;
+    let mut sum = 0.0;
+    for (coord, cell) in lattice.iter_cells() {
+        let w = energy::total_energy(cell).max(0.0);
+        let dx = image(coord.x, center.0, sx);
+        let dy = image(coord.y, center.1, sy);
+        let dz = image(coord.z, center.2, sz);
+        weight += w;
+        sum += w * (dx * dx + dy * dy + dz * dz);
+    }
+    if weight > 0.0 { (sum / weight).sqrt() } else { 0.0 }
+}
+
+/*
+Connected components of cells with total energy above threshold, flood-filled over the periodic
+26-neighborhood (Lattice::neighbors_26), sorted by total energy, largest first.
+*/
+pub fn find_peaks(lattice: &Lattice, threshold: f64) -> Vec<Halo> {
+    let density: Vec<f64> = lattice.cells().iter().map(energy::total_energy).collect();
+    let index = |c: LatticeCoord| lattice.index(c).map(|i| i as usize);
+    let mut visited = vec![false; density.len()];
+    let mut halos = vec![];
+    for (start, _) in lattice.iter_cells() {
+        let Some(i) = index(start) else { continue };
+        if visited[i] || density[i] <= threshold {
+            continue;
+        }
+        visited[i] = true;
+        let mut halo = Halo { peak: start, ..Halo::default() };
+        let mut stack = vec![start];
+        while let Some(coord) = stack.pop() {
+            let Some(ci) = index(coord) else { continue };
+            halo.cells.push(coord);
+            halo.total_energy += density[ci];
+            if density[ci] > density[index(halo.peak).unwrap_or(ci)] {
+                halo.peak = coord;
+            }
+            for n in lattice.neighbors_26(coord) {
+                if let Some(ni) = index(n) && !visited[ni] && density[ni] > threshold {
+                    visited[ni] = true;
+                    stack.push(n);
+                }
+            }
+        }
+        halos.push(halo);
+    }
+    halos.sort_by(|a, b| b.total_energy.total_cmp(&a.total_energy));
+    halos
+}
+
+/*
+Halo mass function: find_peaks halos counted in n_bins log-spaced total-energy bins spanning the
+lightest to the heaviest halo, returned as (geometric bin center, count), empty bins included.
+Halos with non-positive energy are skipped; empty when there are none or n_bins is 0.
+*/
+pub fn mass_function(lattice: &Lattice, threshold: f64, n_bins: usize) -> Vec<(f64, usize)> {
+    let masses: Vec<f64> = find_peaks(lattice, threshold)
+        .iter()
+        .map(|h| h.total_energy)
+        .filter(|&m| m > 0.0)
+        .map(f64::log10)
+        .collect();
+    if masses.is_empty() || n_bins == 0 {
+        return vec![];
+    }
+    let lo = masses.iter().copied().fold(f64::INFINITY, f64::min);
+    let hi = masses.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = (hi - lo) / n_bins as f64;
+    let mut counts = vec![0usize; n_bins];
+    for m in masses {
+        let bin = if width > 0.0 { (((m - lo) / width) as usize).min(n_bins - 1) } else { 0 };
+        counts[bin] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| (10f64.powf(lo + (i as f64 + 0.5) * width), count))
+        .collect()
 }
 
 /*
 Computes density histogram, variance, void/filament fractions, clustering etc.
 */
 pub fn compute_pattern_metrics(lattice: &Lattice) -> PatternMetrics {
-    todo!();
+    compute_pattern_metrics_with_policy(lattice, ClassificationPolicy::default())
 }
 
-/**/
+/*
+compute_pattern_metrics with void/filament thresholds from policy.
+*/
+pub fn compute_pattern_metrics_with_policy(lattice: &Lattice, policy: ClassificationPolicy) -> PatternMetrics {
+    metrics_of_field(lattice, &visualization::energy_density_field(lattice), policy)
+}
+
+/*
+compute_pattern_metrics on variable var_i's density alone (its summed forces per cell), so species can be compared:
+total_energy is that variable's total, and voids / walls / filaments are classified on its own field.
+None if var_i is out of range.
+*/
+pub fn compute_pattern_metrics_for(lattice: &Lattice, var_i: usize) -> Option<PatternMetrics> {
+    if var_i >= VARS {
+        return None;
+    }
+    let density: Vec<f64> = lattice.cells().iter().map(|cell| cell.e[var_i].iter().sum()).collect();
+    Some(metrics_of_field(lattice, &density, ClassificationPolicy::default()))
+}
+
+fn metrics_of_field(lattice: &Lattice, density: &[f64], policy: ClassificationPolicy) -> PatternMetrics {
+    // Moments take one chunked pass over the density, then classification and
+    // the clustering sums share a second (they need the moments from the first).
+    let stats = DensityAccumulator::from_values(density);
+    let n = stats.count().max(1) as f64;
+    let (low, high) = thresholds(density, &stats, policy);
+    let parts = for_chunks(density, |start, chunk| {
+        let mut part = MetricsPart::default();
+        for (offset, &d) in chunk.iter().enumerate() {
+            let i = start + offset;
+            if d < low {
+                part.voids += 1;
+            } else if d > high {
+                part.filaments.push(i);
+            } else {
+                part.walls += 1;
+            }
+            let (sum, bonds) = clustering_terms(lattice, density, stats.mean(), i);
+            part.correlation += sum;
+            part.bonds += bonds;
+        }
+        part
+    });
+    let mut total = MetricsPart::default();
+    for part in parts {
+        total.voids += part.voids;
+        total.walls += part.walls;
+        total.filaments.extend(part.filaments);
+        total.correlation += part.correlation;
+        total.bonds += part.bonds;
+    }
+    let filaments: Vec<LatticeCoord> = total
+        .filaments
+        .iter()
+        .filter_map(|&i| lattice.coord(i as u128))
+        .collect();
+    let variance = stats.variance();
+    let local_clustering = if variance <= 0.0 || total.bonds == 0 {
+        0.0
+    } else {
+        total.correlation / (total.bonds as f64 * variance)
+    };
+    let void_fraction = total.voids as f64 / n;
+    let wall_fraction = total.walls as f64 / n;
+    let filament_fraction = filaments.len() as f64 / n;
+    PatternMetrics {
+        total_energy: stats.sum(),
+        variance,
+        skewness: stats.skewness(),
+        kurtosis: stats.kurtosis(),
+        local_clustering,
+        fractal_dimension: box_counting_dimension(lattice, &filaments),
+        void_fraction,
+        filament_fraction,
+        void_wall_filament_ratio: (void_fraction, wall_fraction, filament_fraction),
+    }
+}
+
+/*
+Per-chunk partial results of compute_pattern_metrics' second pass.
+*/
+#[derive(Default)]
+struct MetricsPart {
+    voids: usize,
+    walls: usize,
+    filaments: Vec<usize>,
+    correlation: f64,
+    bonds: usize,
+}
+
+/*
+(low, high) density thresholds for policy over a density field with moments stats.
+*/
+fn thresholds(density: &[f64], stats: &DensityAccumulator, policy: ClassificationPolicy) -> (f64, f64) {
+    match policy {
+        ClassificationPolicy::Sigma(k) => {
+            let sigma = stats.variance().sqrt();
+            (stats.mean() - k * sigma, stats.mean() + k * sigma)
+        }
+        ClassificationPolicy::Percentile(lo, hi) => {
+            let mut sorted = density.to_vec();
+            sorted.sort_by(f64::total_cmp);
+            let n = sorted.len();
+            let rank = |q: f64| ((q.clamp(0.0, 1.0) * n as f64).round() as usize).min(n);
+            let k_lo = rank(lo);
+            let k_hi = rank(hi);
+            let low = if k_lo < n { sorted[k_lo] } else { f64::INFINITY };
+            let high = if k_hi > 0 { sorted[k_hi - 1] } else { f64::NEG_INFINITY };
+            (low, high)
+        }
+        ClassificationPolicy::Absolute(low, high) => (low, high),
+    }
+}
+
+/*
+(voids, walls, filaments) by total density, with thresholds from policy.
+*/
+pub fn classify_with_policy(lattice: &Lattice, policy: ClassificationPolicy) -> (Vec<LatticeCoord>, Vec<LatticeCoord>, Vec<LatticeCoord>) {
+    let density = visualization::energy_density_field(lattice);
+    let stats = DensityAccumulator::from_values(&density);
+    let (low, high) = thresholds(&density, &stats, policy);
+    void_wall_filament_classification_detailed(lattice, low, high)
+}
+
+/*
+void: density < low_threshold, filament: density > high_threshold, wall: everything else.
+*/
 pub fn void_wall_filament_classification_detailed(lattice: &Lattice, low_threshold: f64, high_threshold: f64) -> (Vec<LatticeCoord>, Vec<LatticeCoord>, Vec<LatticeCoord>) {
-    todo!();
+    let mut voids = vec![];
+    let mut walls = vec![];
+    let mut filaments = vec![];
+    for (coord, cell) in lattice.iter_cells() {
+        let density = energy::total_energy(cell);
+        if density < low_threshold {
+            voids.push(coord);
+        } else if density > high_threshold {
+            filaments.push(coord);
+        } else {
+            walls.push(coord);
+        }
+    }
+    (voids, walls, filaments)
 }
 
 /*
 Basic graph-like clustering or local neighborhood correlation.
+Nearest-neighbor correlation of the density contrast over periodic +x/+y/+z bonds,
+normalized by the variance: 1 for perfectly smooth structure, ≈ 0 for white noise, < 0 for checkerboards.
+0.0 for a constant field.
 */
 pub fn compute_clustering_coefficient(lattice: &Lattice) -> f64 {
-    todo!();
+    let density = visualization::energy_density_field(lattice);
+    let stats = DensityAccumulator::from_values(&density);
+    let variance = stats.variance();
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    // Summed per chunk and then in chunk order, exactly as compute_pattern_metrics does.
+    let parts = for_chunks(&density, |start, chunk| {
+        let mut part = (0.0, 0usize);
+        for i in start..start + chunk.len() {
+            let (sum, bonds) = clustering_terms(lattice, &density, stats.mean(), i);
+            part.0 += sum;
+            part.1 += bonds;
+        }
+        part
+    });
+    let (sum, bonds) = parts.into_iter().fold((0.0, 0), |acc, part| (acc.0 + part.0, acc.1 + part.1));
+    if bonds == 0 { 0.0 } else { sum / (bonds as f64 * variance) }
+}
+
+/*
+Contrast products of cell i with its +x/+y/+z neighbors, and how many such bonds it has.
+*/
+fn clustering_terms(lattice: &Lattice, density: &[f64], mean: f64, i: usize) -> (f64, usize) {
+    let Some(coord) = lattice.coord(i as u128) else {
+        return (0.0, 0);
+    };
+    let mut sum = 0.0;
+    let mut bonds = 0;
+    for d in [Direction::PosX, Direction::PosY, Direction::PosZ] {
+        let neighbor = lattice.shifted(&coord, d.offset());
+        if neighbor == coord {
+            continue;
+        }
+        let j = lattice.index(neighbor).unwrap_or(0) as usize;
+        sum += (density[i] - mean) * (density[j] - mean);
+        bonds += 1;
+    }
+    (sum, bonds)
+}
+
+/*
+Box-counting dimension of a set of cells: slope of log N(s) against log(1/s)
+over box sides s = 1, 2, 4, ... up to the smallest lattice dimension.
+0.0 when the set is empty or only one box size fits.
+*/
+fn box_counting_dimension(lattice: &Lattice, occupied: &[LatticeCoord]) -> f64 {
+    let (sx, sy, sz) = lattice.size();
+    let smallest = sx.min(sy).min(sz);
+    if occupied.is_empty() || smallest < 2 {
+        return 0.0;
+    }
+    let mut points = vec![];
+    let mut side = 1;
+    while side <= smallest {
+        let mut boxes: Vec<(usize, usize, usize)> = occupied
+            .iter()
+            .map(|c| (c.x / side, c.y / side, c.z / side))
+            .collect();
+        boxes.sort_unstable();
+        boxes.dedup();
+        points.push(((1.0 / side as f64).ln(), (boxes.len() as f64).ln()));
+        side *= 2;
+    }
+    let m = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / m;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / m;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    if sxx <= 0.0 { 0.0 } else { sxy / sxx }
 }
 
 /*
@@ -81,9 +847,398 @@ pub fn entropy_check(lattice: &Lattice) -> f64 {
     todo!();
 }
 
+/*
+Spatial cross-correlation ξ_ab(r) = ⟨δa(x) δb(x + d)⟩ of the mean-subtracted per-variable density fields
+of var_a and var_b, binned by separation: bin r averages every distinct periodic offset d (minimum image)
+with round(|d|) = r, for r = 0..=max_r. Returns (r, ξ) for the non-empty bins, so ξ(0) is the covariance.
+Empty if either variable is out of range.
+*/
+pub fn cross_correlation(lattice: &Lattice, var_a: usize, var_b: usize, max_r: f64) -> Vec<(f64, f64)> {
+    if var_a >= VARS || var_b >= VARS || lattice.cells().is_empty() {
+        return vec![];
+    }
+    let contrast = |var: usize| {
+        let field: Vec<f64> = lattice.cells().iter().map(|cell| cell.e[var].iter().sum()).collect();
+        let mean = field.iter().sum::<f64>() / field.len() as f64;
+        field.into_iter().map(|v| v - mean).collect::<Vec<f64>>()
+    };
+    let (a, b) = (contrast(var_a), contrast(var_b));
+    let (sx, sy, sz) = lattice.size();
+    // Distinct residues -(n - 1)/2 ..= n/2 within reach of max_r.
+    let reach = max_r.max(0.0).round() as isize;
+    let offsets = |n: usize| {
+        let n = n as isize;
+        (-(n - 1) / 2..=n / 2).filter(move |d| d.abs() <= reach)
+    };
+    let mut bins = vec![(0.0, 0usize); reach as usize + 1];
+    for dx in offsets(sx) {
+        for dy in offsets(sy) {
+            for dz in offsets(sz) {
+                let r = ((dx * dx + dy * dy + dz * dz) as f64).sqrt().round() as usize;
+                if r > reach as usize {
+                    continue;
+                }
+                let sum: f64 = lattice
+                    .iter_cells()
+                    .zip(a.iter())
+                    .map(|((coord, _), &da)| {
+                        let other = lattice.shifted(&coord, (dx, dy, dz));
+                        lattice.index(other).map_or(0.0, |j| da * b[j as usize])
+                    })
+                    .sum();
+                bins[r].0 += sum / a.len() as f64;
+                bins[r].1 += 1;
+            }
+        }
+    }
+    bins.into_iter()
+        .enumerate()
+        .filter(|(_, (_, n))| *n > 0)
+        .map(|(r, (sum, n))| (r as f64, sum / n as f64))
+        .collect()
+}
+
+/*
+Two-point autocorrelation of variable var_i's density contrast: cross_correlation(lattice, var_i, var_i, max_r).
+*/
+pub fn two_point_correlation(lattice: &Lattice, var_i: usize, max_r: f64) -> Vec<(f64, f64)> {
+    cross_correlation(lattice, var_i, var_i, max_r)
+}
+
+/*
+Integrated autocorrelation time τ = 1 + 2 Σ_k ρ(k) of a metric time series, in samples.
+The sum is cut with Sokal's window: at the first lag M with M ≥ 5 τ(M).
+Samples further apart than ~τ are effectively independent, so thin by τ once the run has equilibrated.
+Returns 1.0 for series shorter than 2 or with zero variance.
+*/
+pub fn autocorrelation_time(series: &[f64]) -> f64 {
+    const WINDOW: f64 = 5.0;
+    let n = series.len();
+    if n < 2 {
+        return 1.0;
+    }
+    let mean = series.iter().sum::<f64>() / n as f64;
+    let centered: Vec<f64> = series.iter().map(|x| x - mean).collect();
+    let c0: f64 = centered.iter().map(|x| x * x).sum::<f64>() / n as f64;
+    if c0 <= 0.0 {
+        return 1.0;
+    }
+    let mut tau = 1.0;
+    for lag in 1..n {
+        let c: f64 = centered[..n - lag]
+            .iter()
+            .zip(centered[lag..].iter())
+            .map(|(a, b)| a * b)
+            .sum::<f64>()
+            / n as f64;
+        tau += 2.0 * c / c0;
+        if lag as f64 >= WINDOW * tau {
+            break;
+        }
+    }
+    tau
+}
+
 /*
 Compare observed projections to expected eigenvectors.
 */
 pub fn eigenmode_health(cell: &CellState, expected_modes: &[OscillationMode]) -> f64 {
     todo!();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{Rng, SeedableRng};
+    use crate::types::ExpressionConstraint;
+    use crate::utils::SimRng;
+
+    fn random_lattice(size: (usize, usize, usize), seed: u64) -> Lattice {
+        let mut rng = SimRng::seed_from_u64(seed);
+        let mut lattice = Lattice::new(size).unwrap();
+        for (_, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = rng.random_range(0.0..5.0f64).powi(2);
+        }
+        lattice
+    }
+
+    #[test]
+    fn welford_matches_two_pass_moments() {
+        let lattice = random_lattice((10, 10, 10), 3);
+        let field = visualization::energy_density_field(&lattice);
+        let n = field.len() as f64;
+        let mean = field.iter().sum::<f64>() / n;
+        let central = |p: i32| field.iter().map(|x| (x - mean).powi(p)).sum::<f64>() / n;
+        let variance = central(2);
+        let streamed = DensityAccumulator::from_lattice(&lattice);
+        assert!((streamed.mean() - mean).abs() < 1e-12 * mean);
+        assert!((streamed.variance() - variance).abs() < 1e-9 * variance);
+        assert!((streamed.skewness() - central(3) / variance.powf(1.5)).abs() < 1e-9);
+        assert!((streamed.kurtosis() - (central(4) / (variance * variance) - 3.0)).abs() < 1e-9);
+        let serial = field.iter().fold(DensityAccumulator::new(), |acc, &x| acc.push(x));
+        assert!((serial.kurtosis() - streamed.kurtosis()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn autocorrelation_time_of_ar1_process() {
+        let phi = 0.8;
+        let mut rng = SimRng::seed_from_u64(9);
+        let mut x = 0.0;
+        let series: Vec<f64> = (0..200_000)
+            .map(|_| {
+                x = phi * x + rng.random_range(-1.0..1.0);
+                x
+            })
+            .collect();
+        let expected = (1.0 + phi) / (1.0 - phi);
+        let tau = autocorrelation_time(&series);
+        assert!((tau - expected).abs() < 0.05 * expected, "{tau} vs {expected}");
+        assert_eq!(autocorrelation_time(&[1.0]), 1.0);
+        assert_eq!(autocorrelation_time(&[2.0; 10]), 1.0);
+    }
+
+    #[test]
+    fn report_renders_every_field() {
+        let mut per_variable_error = [0.0; VARS];
+        per_variable_error[3] = 2e-4;
+        let mut per_force_error = [0.0; FORCES];
+        per_force_error[1] = 7e-6;
+        let report = ConservationReport {
+            global_energy_error: 1.5e-9,
+            per_variable_error,
+            per_force_error,
+            constraint_violations: vec!["var 0 FixedTotal off by 0.1".into()],
+        };
+        let text = report.to_string();
+        assert!(text.contains("1.500e-9"), "{text}");
+        assert!(text.contains("(var 3)"), "{text}");
+        assert!(text.contains("(force 1)"), "{text}");
+        assert!(text.contains("var 0 FixedTotal off by 0.1"), "{text}");
+    }
+
+    #[test]
+    fn budget_breaks_down_known_channel_totals() {
+        let mut lattice = Lattice::new((2, 2, 2)).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[1][3] = 1.0;
+            cell.e[4][0] = coord.x as f64;
+        }
+        let budget = energy_budget(&lattice);
+        assert_eq!(budget.per_channel[1][3], 8.0);
+        assert_eq!(budget.per_channel[4][0], 4.0);
+        assert_eq!(budget.per_variable, [0.0, 8.0, 0.0, 0.0, 4.0]);
+        assert_eq!(budget.per_force, [4.0, 0.0, 0.0, 8.0]);
+        assert_eq!(budget.total, 12.0);
+    }
+
+    fn deposit(lattice: &mut Lattice, (x, y, z): (usize, usize, usize), energy: f64) {
+        lattice.at_mut(LatticeCoord { x, y, z }).unwrap().e[0][0] = energy;
+    }
+
+    #[test]
+    fn center_of_mass_sits_on_a_single_hot_cell() {
+        let mut lattice = Lattice::new((8, 6, 5)).unwrap();
+        deposit(&mut lattice, (7, 2, 4), 3.0);
+        let (x, y, z) = center_of_mass(&lattice);
+        assert!((x - 7.0).abs() < 1e-9 && (y - 2.0).abs() < 1e-9 && (z - 4.0).abs() < 1e-9);
+        assert!(rms_spread(&lattice) < 1e-9);
+
+        // x = 7 and x = 1 are two cells apart across the periodic boundary, centered on x = 0.
+        deposit(&mut lattice, (1, 2, 4), 3.0);
+        assert!(center_of_mass(&lattice).0.abs() < 1e-9);
+        assert!((rms_spread(&lattice) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn diagonal_and_wrapped_cells_join_one_halo() {
+        let mut lattice = Lattice::new((10, 10, 4)).unwrap();
+        for (coord, energy) in [((1, 1, 1), 5.0), ((2, 2, 2), 3.0), ((0, 0, 0), 2.0), ((9, 9, 3), 1.0)] {
+            deposit(&mut lattice, coord, energy);
+        }
+        deposit(&mut lattice, (5, 6, 1), 1.0);
+        deposit(&mut lattice, (6, 6, 1), 1.5);
+        let halos = find_peaks(&lattice, 0.5);
+        assert_eq!(halos.len(), 2);
+        assert_eq!(halos[0].cells.len(), 4);
+        assert_eq!(halos[0].total_energy, 11.0);
+        assert_eq!(halos[0].peak, LatticeCoord { x: 1, y: 1, z: 1 });
+        assert_eq!(halos[1].cells.len(), 2);
+        assert_eq!(halos[1].peak, LatticeCoord { x: 6, y: 6, z: 1 });
+    }
+
+    #[test]
+    fn mass_function_bins_halos_by_log_energy() {
+        let mut lattice = Lattice::new((20, 4, 4)).unwrap();
+        for (x, energy) in [(0, 1.0), (3, 1.2), (6, 10.0), (9, 100.0), (12, 95.0)] {
+            deposit(&mut lattice, (x, 0, 0), energy);
+        }
+        let bins = mass_function(&lattice, 0.5, 2);
+        assert_eq!(bins.iter().map(|&(_, count)| count).collect::<Vec<_>>(), vec![2, 3]);
+        assert!((bins[0].0 - 10f64.sqrt()).abs() < 1e-9);
+        assert!((bins[1].0 - 10f64.powf(1.5)).abs() < 1e-9);
+        assert!(mass_function(&lattice, 0.5, 0).is_empty());
+        assert!(mass_function(&Lattice::new((2, 2, 2)).unwrap(), 0.5, 3).is_empty());
+    }
+
+    #[test]
+    fn pattern_metrics_match_the_serial_reference() {
+        let lattice = random_lattice((20, 18, 16), 4);
+        let metrics = compute_pattern_metrics(&lattice);
+        let stats = DensityAccumulator::from_lattice(&lattice);
+        let sigma = stats.variance().sqrt();
+        let (voids, walls, filaments) =
+            void_wall_filament_classification_detailed(&lattice, stats.mean() - sigma, stats.mean() + sigma);
+        let n = (voids.len() + walls.len() + filaments.len()) as f64;
+        assert_eq!(metrics.void_fraction, voids.len() as f64 / n);
+        assert_eq!(metrics.filament_fraction, filaments.len() as f64 / n);
+        assert_eq!(metrics.local_clustering, compute_clustering_coefficient(&lattice));
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn pattern_metrics_are_bit_identical_across_thread_counts() {
+        let lattice = random_lattice((20, 18, 16), 4);
+        let on = |threads| format!("{:?}", crate::utils::with_threads(threads, || compute_pattern_metrics(&lattice)));
+        assert_eq!(on(1), on(4));
+    }
+
+    #[test]
+    fn each_policy_gives_its_documented_fractions_on_a_uniform_field() {
+        let mut rng = SimRng::seed_from_u64(11);
+        let mut lattice = Lattice::new((20, 20, 25)).unwrap();
+        for (_, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = rng.random_range(0.0..1.0);
+        }
+        let fractions = |policy| {
+            let (voids, walls, filaments) = classify_with_policy(&lattice, policy);
+            let n = lattice.cells().len() as f64;
+            (voids.len() as f64 / n, walls.len() as f64 / n, filaments.len() as f64 / n)
+        };
+        let (v, w, f) = fractions(ClassificationPolicy::Percentile(0.2, 0.7));
+        assert_eq!((v, w, f), (0.2, 0.5, 0.3));
+        let (v, w, f) = fractions(ClassificationPolicy::Absolute(0.25, 0.5));
+        assert!((v - 0.25).abs() < 0.02 && (w - 0.25).abs() < 0.02 && (f - 0.5).abs() < 0.02);
+        // A uniform field has σ = 1/√12, so mean ± σ holds 2/√12 of the cells.
+        let (v, w, f) = fractions(ClassificationPolicy::Sigma(1.0));
+        assert!((w - 1.0 / 3f64.sqrt()).abs() < 0.02 && (v - f).abs() < 0.03);
+        let metrics = compute_pattern_metrics_with_policy(&lattice, ClassificationPolicy::Percentile(0.2, 0.7));
+        assert_eq!((metrics.void_fraction, metrics.filament_fraction), (0.2, 0.3));
+        assert_eq!(compute_pattern_metrics(&lattice).void_fraction, fractions(ClassificationPolicy::default()).0);
+    }
+
+    #[test]
+    fn metrics_differing_only_in_variance_report_that_field() {
+        let a = PatternMetrics { variance: 1.0, total_energy: 5.0, ..PatternMetrics::default() };
+        let b = PatternMetrics { variance: 1.5, ..a.clone() };
+        assert!(a.approx_eq(&a.clone(), 0.0));
+        assert!(!a.approx_eq(&b, 0.1));
+        assert!(a.approx_eq(&b, 0.6));
+        let diff = a.diff(&b, 0.1);
+        assert_eq!(diff.len(), 1);
+        assert!(diff[0].starts_with("variance"), "{diff:?}");
+        let nan = PatternMetrics { skewness: f64::NAN, ..a.clone() };
+        assert!(!nan.approx_eq(&nan.clone(), f64::INFINITY));
+    }
+
+    #[test]
+    fn broken_cell_is_reported_at_its_coordinate() {
+        let mut lattice = Lattice::new((3, 3, 3)).unwrap();
+        for (_, cell) in lattice.iter_cells_mut() {
+            cell.e[0] = [0.25; FORCES];
+        }
+        let mut constraints = ConstraintSet::default();
+        constraints.var_constraints[0] = VariableConstraint::FixedTotal(1.0);
+        constraints.expr_constraints[0] = ExpressionConstraint { locked: true, force_pct: [0.25; FORCES] };
+        assert!(constraint_violation_cells(&lattice, &constraints, 1e-9).is_empty());
+
+        let broken = LatticeCoord { x: 1, y: 2, z: 0 };
+        lattice.at_mut(broken).unwrap().e[0][1] = 0.5;
+        let violations = constraint_violation_cells(&lattice, &constraints, 1e-9);
+        assert!(!violations.is_empty());
+        assert!(violations.iter().all(|(coord, _)| *coord == broken));
+        assert!(violations.iter().any(|(_, reason)| reason.starts_with("var 0 total")));
+        assert!(verify_constraints(&lattice, &constraints).constraint_violations[0].starts_with("(1, 2, 0): var 0"));
+    }
+
+    fn mixed_magnitude_lattice() -> Lattice {
+        let mut lattice = Lattice::new((40, 40, 40)).unwrap();
+        for (c, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = 1e8 * ((c.x * 31 + c.y * 17 + c.z * 7) % 13) as f64 + 1e-7 * c.z as f64;
+            cell.e[3][1] = 0.1;
+        }
+        lattice
+    }
+
+    #[test]
+    fn stable_total_beats_the_plain_sum() {
+        let lattice = mixed_magnitude_lattice();
+        let steps: u64 = lattice.iter_cells().map(|(c, _)| ((c.x * 31 + c.y * 17 + c.z * 7) % 13) as u64).sum();
+        let z_sum: u64 = lattice.iter_cells().map(|(c, _)| c.z as u64).sum();
+        let exact = 1e8 * steps as f64 + (1e-7 * z_sum as f64 + 0.1 * lattice.cells().len() as f64);
+        let plain: f64 = lattice.cells().iter().map(energy::total_energy).sum();
+        let stable = total_energy_stable(&lattice);
+        assert!((stable - exact).abs() <= (plain - exact).abs());
+        assert!((stable - exact).abs() <= 1e-15 * exact);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn stable_total_is_bit_identical_across_thread_counts() {
+        let lattice = mixed_magnitude_lattice();
+        let on = |threads| crate::utils::with_threads(threads, || total_energy_stable(&lattice)).to_bits();
+        assert_eq!(on(1), on(4));
+        assert_eq!(on(1), on(7));
+    }
+
+    #[test]
+    fn assert_conserved_passes_within_tolerance_and_explains_failures() {
+        let mut lattice = Lattice::new((2, 2, 2)).unwrap();
+        for (_, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = 1.0;
+        }
+        assert!(assert_conserved(&lattice, 8.0, 1e-12).is_ok());
+        assert!(assert_conserved(&lattice, 8.0 * (1.0 + 1e-9), 1e-6).is_ok());
+        let error = assert_conserved(&lattice, 7.0, 1e-6).unwrap_err();
+        assert!(error.contains("total 8e0 vs initial 7e0") && error.contains("tolerance 1e-6"), "{error}");
+        lattice.at_mut(LatticeCoord::default()).unwrap().e[0][0] = f64::NAN;
+        assert!(assert_conserved(&lattice, 8.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn self_cross_correlation_is_the_autocorrelation() {
+        let mut lattice = Lattice::new((8, 6, 5)).unwrap();
+        for (c, cell) in lattice.iter_cells_mut() {
+            cell.e[1][0] = ((c.x * 7 + c.y * 3 + c.z * 5) % 11) as f64;
+            cell.e[3][2] = (std::f64::consts::TAU * c.x as f64 / 8.0).cos() + 0.5 * cell.e[1][0];
+        }
+        let auto = two_point_correlation(&lattice, 1, 3.0);
+        assert_eq!(cross_correlation(&lattice, 1, 1, 3.0), auto);
+        let stats = DensityAccumulator::from_values(&lattice.cells().iter().map(|c| c.e[1][0]).collect::<Vec<_>>());
+        assert_eq!(auto[0].0, 0.0);
+        assert!((auto[0].1 - stats.variance()).abs() < 1e-9);
+        let ab = cross_correlation(&lattice, 1, 3, 3.0);
+        let ba = cross_correlation(&lattice, 3, 1, 3.0);
+        assert!(ab.iter().zip(&ba).all(|(p, q)| p.0 == q.0 && (p.1 - q.1).abs() < 1e-9));
+        assert!(cross_correlation(&lattice, VARS, 1, 2.0).is_empty());
+    }
+
+    #[test]
+    fn per_variable_metrics_tell_distributions_apart() {
+        let mut lattice = Lattice::new((8, 8, 8)).unwrap();
+        for (c, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = 1.0 + 0.5 * (std::f64::consts::TAU * c.x as f64 / 8.0).cos();
+            cell.e[2][1] = if (c.x * 3 + c.y * 5 + c.z * 7) % 4 == 0 { 3.0 } else { 1.0 };
+        }
+        let smooth = compute_pattern_metrics_for(&lattice, 0).unwrap();
+        let speckled = compute_pattern_metrics_for(&lattice, 2).unwrap();
+        assert!(!smooth.approx_eq(&speckled, 1e-6));
+        assert!(smooth.local_clustering > speckled.local_clustering);
+        assert!((smooth.total_energy - 512.0).abs() < 1e-9);
+        assert!(compute_pattern_metrics_for(&lattice, VARS).is_none());
+        let mut alone = lattice.clone();
+        for (_, cell) in alone.iter_cells_mut() {
+            cell.e[2] = [0.0; FORCES];
+        }
+        assert!(compute_pattern_metrics(&alone).approx_eq(&smooth, 0.0));
+    }
+}
@@ -0,0 +1,112 @@
+#![forbid(unsafe_code)]
+
+/*
+Purpose: Persist scalar run metrics for later plotting.
+
+Uses evolution::Simulation and conservation metrics.
+Observes only; never mutates the simulation.
+*/
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::evolution::Simulation;
+
+/**/
+pub const CSV_HEADER: &str = "step,time,total_energy,conservation_error,variance,void_fraction";
+
+/*
+One CSV row per logged step under CSV_HEADER.
+*/
+pub struct MetricsCsvLogger {
+    writer: BufWriter<File>,
+    error: Option<io::Error>,
+}
+
+/**/
+impl MetricsCsvLogger {
+    /*
+    Creates (truncates) path and writes the header.
+    */
+    pub fn new(path: impl AsRef<Path>) -> io::Result<MetricsCsvLogger> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{CSV_HEADER}")?;
+        Ok(MetricsCsvLogger { writer, error: None })
+    }
+
+    /**/
+    pub fn log(
+        &mut self,
+        step: usize,
+        time: f64,
+        total_energy: f64,
+        conservation_error: f64,
+        variance: f64,
+        void_fraction: f64,
+    ) -> io::Result<()> {
+        writeln!(self.writer, "{step},{time},{total_energy},{conservation_error},{variance},{void_fraction}")
+    }
+
+    /*
+    Logs the simulation's current metrics.
+    Made for evolve_until callbacks, which cannot return errors:
+    the first IO error is kept for finish and later calls do nothing.
+    */
+    pub fn record(&mut self, sim: &Simulation) {
+        if self.error.is_some() {
+            return;
+        }
+        let metrics = sim.compute_pattern_metrics();
+        let result = self.log(
+            sim.step,
+            sim.time,
+            metrics.total_energy,
+            sim.verify_energy_conservation(),
+            metrics.variance,
+            metrics.void_fraction,
+        );
+        if let Err(e) = result {
+            self.error = Some(e);
+        }
+    }
+
+    /**/
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /*
+    Surfaces any error kept by record, then flushes.
+    */
+    pub fn finish(mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lattice::Lattice;
+    use crate::types::{FORCES, VARS};
+
+    #[test]
+    fn logged_rows_follow_the_header() {
+        let path = std::env::temp_dir().join(format!("entropic_void_metrics_{}.csv", std::process::id()));
+        let mut logger = MetricsCsvLogger::new(&path).unwrap();
+        let lattice = Lattice::new((2, 2, 2)).unwrap();
+        let mut sim = Simulation::new(lattice, Default::default(), [[0.1; FORCES]; VARS], Default::default()).unwrap();
+        sim.evolve_until(0.3, 0.1, |s| logger.record(s)).unwrap();
+        logger.finish().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let mut lines = text.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        let rows: Vec<&str> = lines.collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows.iter().all(|row| row.split(',').count() == CSV_HEADER.split(',').count()));
+        assert!(rows[0].starts_with("1,"));
+    }
+}
@@ -8,22 +8,32 @@ Called by: init, evolution, conservation.
 */
 
 use crate::types::{
-    CellState, ConstraintSet, ExpressionConstraint, FORCES, VARS, VariableConstraint,
+    CellState, ConstraintSet, ExpressionConstraint, FORCES, N_FLATTENED, VARS, VariableConstraint,
 };
 
 /**/
 pub fn total_energy(cell: &CellState) -> f64 {
-    todo!();
+    cell.e.iter().flatten().sum()
 }
 
 /**/
 pub fn per_variable(cell: &CellState) -> [f64; VARS] {
-    todo!();
+    let mut totals = [0.0; VARS];
+    for (total, row) in totals.iter_mut().zip(cell.e.iter()) {
+        *total = row.iter().sum();
+    }
+    totals
 }
 
 /**/
 pub fn per_force(cell: &CellState) -> [f64; FORCES] {
-    todo!();
+    let mut totals = [0.0; FORCES];
+    for row in cell.e.iter() {
+        for (total, value) in totals.iter_mut().zip(row.iter()) {
+            *total += value;
+        }
+    }
+    totals
 }
 
 /*
@@ -34,15 +44,51 @@ pub fn apply_expression_constraints(
     cell: &mut CellState,
     constraints: &[ExpressionConstraint; VARS],
 ) {
-    todo!();
+    for (row, constraint) in cell.e.iter_mut().zip(constraints.iter()) {
+        if !constraint.locked {
+            continue;
+        }
+        let total: f64 = row.iter().sum();
+        for (value, pct) in row.iter_mut().zip(constraint.force_pct.iter()) {
+            *value = total * pct;
+        }
+    }
 }
 
 /*
 Effect:
 For FixedTotal(t), scale E[i][*] to sum to t.
+For FixedRatio(r) on variable i, scale E[i][*] to r[i] / Σr of the cell total.
+A row with no energy to scale is filled evenly across forces.
 */
 pub fn apply_variable_constraints(cell: &mut CellState, constraints: &[VariableConstraint; VARS]) {
-    todo!();
+    let cell_total = total_energy(cell);
+    for (i, constraint) in constraints.iter().enumerate() {
+        let target = match constraint {
+            VariableConstraint::Free => continue,
+            VariableConstraint::FixedTotal(t) => *t,
+            VariableConstraint::FixedRatio(ratios) => {
+                let sum: f64 = ratios.iter().sum();
+                if sum <= 0.0 {
+                    continue;
+                }
+                cell_total * ratios[i] / sum
+            }
+        };
+        scale_row(&mut cell.e[i], target);
+    }
+}
+
+fn scale_row(row: &mut [f64; FORCES], target: f64) {
+    let current: f64 = row.iter().sum();
+    if current.abs() > f64::EPSILON {
+        let factor = target / current;
+        for value in row.iter_mut() {
+            *value *= factor;
+        }
+    } else {
+        row.fill(target / FORCES as f64);
+    }
 }
 
 /*
@@ -51,7 +97,8 @@ Call apply_expression_constraints then apply_variable_constraints.
 Optionally correct tiny numeric drift to maintain global consistency (if global pass).
 */
 pub fn project_energy(cell: &mut CellState, constraints: &ConstraintSet) {
-    todo!();
+    apply_expression_constraints(cell, &constraints.expr_constraints);
+    apply_variable_constraints(cell, &constraints.var_constraints);
 }
 
 /**/
@@ -59,10 +106,104 @@ pub fn per_variable_percentage(cell: &CellState, var_i: usize) -> [f64; FORCES]
     todo!();
 }
 
+/*
+Occupation spread of a cell: Shannon entropy of p_k = E_k / Σ E over the N_FLATTENED channels,
+divided by ln(N_FLATTENED) to land in [0, 1].
+0 when all energy sits in one channel, 1 when it is spread evenly. Negative channels count as empty;
+a cell with no positive energy has temperature 0.
+*/
+pub fn effective_temperature(cell: &CellState) -> f64 {
+    let total: f64 = cell.e.iter().flatten().filter(|v| **v > 0.0).sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    let entropy: f64 = cell
+        .e
+        .iter()
+        .flatten()
+        .filter(|v| **v > 0.0)
+        .map(|v| {
+            let p = v / total;
+            -p * p.ln()
+        })
+        .sum();
+    entropy / (N_FLATTENED as f64).ln()
+}
+
 /*
 Checks:
 Non-negative, finite values.
 */
 pub fn is_valid(cell: &CellState, tolerance: f64) -> bool {
-    todo!();
+    cell.e.iter().flatten().all(|v| v.is_finite() && *v >= -tolerance)
+}
+
+/*
+Effect:
+Zero negative channels and take the deficit proportionally from the positive ones,
+so the cell total is unchanged.
+Returns false (cell untouched) when the positive channels cannot cover the deficit.
+*/
+pub fn clamp_negative(cell: &mut CellState) -> bool {
+    clamp_negative_rows(cell, &[true; VARS])
+}
+
+/*
+clamp_negative restricted to the variables with rows[i] set: other rows neither count toward
+the deficit nor give up any surplus, so they are left exactly as they are.
+*/
+pub fn clamp_negative_rows(cell: &mut CellState, rows: &[bool; VARS]) -> bool {
+    let selected = || cell.e.iter().zip(rows).filter(|(_, on)| **on).flat_map(|(row, _)| row.iter());
+    let deficit: f64 = selected().filter(|v| **v < 0.0).map(|v| -v).sum();
+    if deficit == 0.0 {
+        return true;
+    }
+    let surplus: f64 = selected().filter(|v| **v > 0.0).sum();
+    if surplus < deficit {
+        return false;
+    }
+    let factor = (surplus - deficit) / surplus;
+    for (row, _) in cell.e.iter_mut().zip(rows).filter(|(_, on)| **on) {
+        for value in row.iter_mut() {
+            if *value < 0.0 {
+                *value = 0.0;
+            } else {
+                *value *= factor;
+            }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_channel_cells_are_cold_and_even_cells_are_hottest() {
+        let mut cell = CellState::default();
+        assert_eq!(effective_temperature(&cell), 0.0);
+        cell.e[2][1] = 5.0;
+        assert_eq!(effective_temperature(&cell), 0.0);
+        let even = CellState { e: [[0.7; FORCES]; VARS] };
+        assert!((effective_temperature(&even) - 1.0).abs() < 1e-12);
+        cell.e[0][0] = 1.0;
+        let mixed = effective_temperature(&cell);
+        assert!(mixed > 0.0 && mixed < 1.0);
+    }
+
+    #[test]
+    fn clamp_rows_leaves_unselected_variables_alone() {
+        let mut cell = CellState::default();
+        cell.e[0] = [-1.0, 3.0, 0.0, 0.0];
+        cell.e[1] = [-2.0, 5.0, 0.0, 0.0];
+        let mut rows = [false; VARS];
+        rows[0] = true;
+        assert!(clamp_negative_rows(&mut cell, &rows));
+        assert_eq!(cell.e[0], [0.0, 2.0, 0.0, 0.0]);
+        assert_eq!(cell.e[1], [-2.0, 5.0, 0.0, 0.0]);
+        cell.e[0] = [-3.0, 1.0, 0.0, 0.0];
+        assert!(!clamp_negative_rows(&mut cell, &rows));
+        assert_eq!(cell.e[0], [-3.0, 1.0, 0.0, 0.0]);
+    }
 }
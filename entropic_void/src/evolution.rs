@@ -6,67 +6,728 @@ Purpose: High-level simulation orchestration.
 Central orchestrator calling:
 lattice, redistribution, transport, energy, conservation.
 */
-use crate::conservation::PatternMetrics;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use crate::conservation::{self, PatternMetrics};
+use crate::energy;
 use crate::lattice::Lattice;
-use crate::types::{ConstraintSet, RedistributionMatrix, FORCES, VARS};
+use crate::redistribution;
+use crate::source::SourceTerm;
+use crate::transport::{self, TransportScheme};
+use crate::types::{split_index, CellState, ConstraintSet, LatticeCoord, RedistributionMatrix, FORCES, N_FLATTENED, VARS};
+use crate::utils;
+use crate::visualization;
+
+/*
+Upper bound on recorded snapshots unless snapshot_cap is changed.
+*/
+pub const DEFAULT_SNAPSHOT_CAP: usize = 256;
+
+/*
+Ceiling scale_coupling clamps each coupling entry to.
+*/
+pub const MAX_COUPLING: f64 = 1e3;
+
+/*
+Largest redistribution::exponential_norm_error preflight accepts for an antisymmetric matrix.
+*/
+pub const PREFLIGHT_NORM_TOLERANCE: f64 = 1e-8;
+
+/*
+What step does when a channel ends up negative.
+*/
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NegativePolicy {
+    // Leave negative values in place.
+    Allow,
+    // Zero them and take the deficit from the same cell's positive channels (conserving).
+    #[default]
+    Clamp,
+    // Fail the step.
+    Error,
+}
+
+/*
+What step does with a cell holding NaN or infinite energy before transport can spread it to neighbors.
+Offending cells are listed by Simulation::non_finite_cells either way.
+*/
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NanPolicy {
+    // Fail the step before transport.
+    #[default]
+    Abort,
+    // Zero every channel of the cell (losing its energy) and continue.
+    Zero,
+    // Skip the scan; non-finite values spread and enforce_negative_policy reports them after the step.
+    Ignore,
+}
+
+/*
+How step_redistribution integrates dE/dt = R·E.
+*/
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum RedistributionSolver {
+    // One exp(R * dt) per step, shared by every cell.
+    #[default]
+    Exact,
+    // Per-cell adaptive RK4 (redistribution::evolve_adaptive) with the given local error tolerance.
+    AdaptiveRk { tol: f64 },
+}
+
+/*
+Why evolve_until_converged returned.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    // The per-step change in density variance dropped below tol.
+    Converged,
+    // max_steps were taken first.
+    MaxSteps,
+}
 
 /**/
-#[derive(Default)]
+#[derive(Clone)]
 pub struct Simulation {
     pub lattice: Lattice,
     pub redistribution: RedistributionMatrix,
+    pub solver: RedistributionSolver,
     pub coupling: [[f64; FORCES]; VARS],
+    // How same-variable transport exchanges across bonds. LinearDiffusion is faster, but step_back refuses it.
+    pub transport_scheme: TransportScheme,
+    // Inter-variable transport rates, see transport::distribute_cross_variable. Zero by default.
+    pub cross_coupling: [[f64; VARS]; VARS],
+    // Per-Direction factor on coupling, transport::ISOTROPIC by default.
+    pub direction_scale: [f64; 6],
     pub constraints: ConstraintSet,
+    // Per-cell ConstraintSets that replace constraints entirely at their coord (see constraints_at). Empty by default.
+    pub constraint_overrides: HashMap<LatticeCoord, ConstraintSet>,
+    // Forcing applied after transport in every step; shared between snapshots of the run.
+    pub source: Option<Arc<dyn SourceTerm>>,
+    // Re-run energy::project_energy on every cell after transport.
+    // Keeps FixedTotal / locked constraints exact despite diffusion, at one more pass over the lattice
+    // per step; projection is not conservative, so global totals can shift to satisfy the constraints.
+    pub reproject_after_transport: bool,
+    pub negative_policy: NegativePolicy,
+    pub nan_policy: NanPolicy,
     pub time: f64,
+    // Physical time per unit of time, for relating runs to real units. Unset by default.
+    pub time_scale: Option<f64>,
     pub step: usize,
-    // Maybe initial energy snapshots for conservation checks.
+    // Total energy at construction, for conservation checks.
+    pub initial_energy: f64,
+    // Total energy per cell at construction, in index order, for visualization::delta_field.
+    pub initial_density: Vec<f64>,
+    // Oldest snapshots are dropped once this many are held.
+    pub snapshot_cap: usize,
+    // Relative energy error above which conservation_warning is raised after evolve steps. Off by default.
+    pub conservation_warn_threshold: Option<f64>,
+    conservation_warning: bool,
+    record_every: usize,
+    snapshots: VecDeque<(f64, Lattice)>,
+    probes: HashMap<LatticeCoord, Vec<(f64, CellState)>>,
+    // find_peaks threshold for track_largest_halo, and the (time, total_energy, cells) series.
+    halo_threshold: Option<f64>,
+    largest_halo: Vec<(f64, f64, usize)>,
+    // Whether track_variable_series is on, and the (time, per-variable totals, residual) series.
+    track_variables: bool,
+    variable_series: Vec<(f64, [f64; VARS], f64)>,
+    // Per-cell total energy summed over evolve steps, and the number of steps summed.
+    time_average: Option<(Vec<f64>, usize)>,
+    // Variables held fixed by freeze_variable.
+    frozen: [bool; VARS],
+    // Cells the last quarantine scan found non-finite.
+    non_finite: Vec<LatticeCoord>,
 }
 
 /**/
 impl Simulation {
     /*
     Simple constructor.
+    Errors on a zero-cell lattice, where conservation checks and metrics are meaningless.
     */
-    pub fn new(lattice: Lattice, redistribution: RedistributionMatrix, coupling: [[f64; FORCES]; VARS], constraints: ConstraintSet) -> Simulation {
-        todo!();
+    pub fn new(lattice: Lattice, redistribution: RedistributionMatrix, coupling: [[f64; FORCES]; VARS], constraints: ConstraintSet) -> Result<Simulation, &'static str> {
+        if lattice.cells().is_empty() {
+            return Err("lattice has no cells");
+        }
+        let initial_energy = lattice.iter_cells().map(|(_, cell)| energy::total_energy(cell)).sum();
+        let initial_density = visualization::energy_density_field(&lattice);
+        Ok(Simulation {
+            lattice,
+            redistribution,
+            solver: RedistributionSolver::default(),
+            coupling,
+            transport_scheme: TransportScheme::default(),
+            cross_coupling: [[0.0; VARS]; VARS],
+            direction_scale: transport::ISOTROPIC,
+            constraints,
+            constraint_overrides: HashMap::new(),
+            source: None,
+            reproject_after_transport: false,
+            negative_policy: NegativePolicy::default(),
+            nan_policy: NanPolicy::default(),
+            time: 0.0,
+            time_scale: None,
+            step: 0,
+            initial_energy,
+            initial_density,
+            snapshot_cap: DEFAULT_SNAPSHOT_CAP,
+            conservation_warn_threshold: None,
+            conservation_warning: false,
+            record_every: 0,
+            snapshots: VecDeque::new(),
+            probes: HashMap::new(),
+            halo_threshold: None,
+            largest_halo: vec![],
+            track_variables: false,
+            variable_series: vec![],
+            time_average: None,
+            frozen: [false; VARS],
+            non_finite: vec![],
+        })
+    }
+
+    /*
+    Hold variable var fixed: redistribution and transport leave its rows out,
+    and step restores its per-cell values after projection, before the negative policy (whose Clamp only
+    draws on free variables, so restoring cannot undo part of a clamp and create energy).
+    Exchange between frozen and free variables stops, so a matrix that conserved the total
+    only conserves it among the free variables while anything is frozen.
+    */
+    pub fn freeze_variable(&mut self, var: usize) -> Result<(), &'static str> {
+        *self.frozen.get_mut(var).ok_or("variable index out of range")? = true;
+        Ok(())
+    }
+
+    /**/
+    pub fn thaw_variable(&mut self, var: usize) -> Result<(), &'static str> {
+        *self.frozen.get_mut(var).ok_or("variable index out of range")? = false;
+        Ok(())
+    }
+
+    /**/
+    pub fn is_frozen(&self, var: usize) -> bool {
+        self.frozen.get(var).copied().unwrap_or(false)
+    }
+
+    /*
+    Rough floating-point operation count of one step(dt) with the Exact solver on a lattice of this size:
+    the shared propagator exp(R dt), an N_FLATTENED² mat-vec and constraint projection per cell,
+    and one exchange per channel on each of a cell's three forward bonds. Saturates at u128::MAX.
+    For sizing runs before allocating (see Lattice::estimated_bytes), not for timing.
+    */
+    pub fn estimated_step_cost(size: (usize, usize, usize)) -> u128 {
+        const N: u128 = N_FLATTENED as u128;
+        // Scaling and squaring: Taylor terms plus ~10 squarings, one N³ multiply-add each.
+        let propagator = (redistribution::EXPONENTIAL_TERMS as u128 + 10) * 2 * N * N * N;
+        // Mat-vec, projection (a few passes over the channels), 3 bonds × channels × ~30 (exp included).
+        let per_cell = 2 * N * N + 4 * N + 3 * N * 30;
+        Lattice::cell_count_size(size)
+            .and_then(|count| count.checked_mul(per_cell))
+            .and_then(|cost| cost.checked_add(propagator))
+            .unwrap_or(u128::MAX)
+    }
+
+    /*
+    time * time_scale, or None while no time_scale is set.
+    */
+    pub fn physical_time(&self) -> Option<f64> {
+        self.time_scale.map(|scale| self.time * scale)
+    }
+
+    /*
+    Independent copy of the whole state (lattice, parameters, time, initial_energy, recorded snapshots)
+    for branching a run: stepping either never affects the other.
+    */
+    pub fn snapshot(&self) -> Simulation {
+        self.clone()
+    }
+
+    /*
+    Multiply every coupling entry by factor, clamped to [0, MAX_COUPLING].
+    Returns the new transport::max_stable_dt so a caller can warn if its dt is now too large.
+    */
+    pub fn scale_coupling(&mut self, factor: f64) -> Result<f64, &'static str> {
+        if !factor.is_finite() || factor < 0.0 {
+            return Err("coupling factor must be finite and non-negative");
+        }
+        for c in self.coupling.iter_mut().flatten() {
+            *c = (*c * factor).clamp(0.0, MAX_COUPLING);
+        }
+        Ok(transport::max_stable_dt(&self.coupling, &self.direction_scale))
+    }
+
+    /*
+    Checks dt before a run: it must be positive and finite, within transport::max_stable_dt,
+    and, when the redistribution matrix is antisymmetric, keep exp(R * dt) orthogonal to PREFLIGHT_NORM_TOLERANCE.
+    Non-antisymmetric matrices are not meant to preserve the norm, so that check is skipped for them.
+    */
+    pub fn preflight(&self, dt: f64) -> Result<(), String> {
+        if !dt.is_finite() || dt <= 0.0 {
+            return Err(format!("dt = {dt} must be positive and finite"));
+        }
+        let max_dt = transport::max_stable_dt(&self.coupling, &self.direction_scale);
+        if dt > max_dt {
+            return Err(format!("dt = {dt} exceeds the transport stability limit {max_dt:.3e}"));
+        }
+        if utils::is_antisymmetric(&self.redistribution.a, 0.0) {
+            let error = redistribution::exponential_norm_error(&self.redistribution, dt);
+            if error.is_nan() || error > PREFLIGHT_NORM_TOLERANCE {
+                return Err(format!(
+                    "dt = {dt} gives redistribution norm error {error:.3e} (limit {PREFLIGHT_NORM_TOLERANCE:e})"
+                ));
+            }
+        }
+        Ok(())
     }
 
     /*
     Calls:
-        self.step_redistribution(dt) (which calls redistribution::evolve_exact or evolve_adaptive and energy::project_energy per cell).
+        self.step_redistribution(dt) (which uses self.solver and energy::project_energy per cell).
+        self.quarantine_non_finite() (self.nan_policy).
         self.step_transport(dt) (which calls transport::distribute_to_neighbors).
+        self.source, if any, at the step's start time.
     Updates:
         self.time += dt;
         self.step += 1;
     Returns:
         Ok or error.
     */
-    pub fn step(&mut self, dt: f64, use_adaptive: bool) -> Result<(), &'static str> {
-        todo!();
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "trace", skip_all, fields(step = self.step, dt)))]
+    pub fn step(&mut self, dt: f64) -> Result<(), &'static str> {
+        if !dt.is_finite() || dt <= 0.0 {
+            return Err("dt must be positive and finite");
+        }
+        #[cfg(feature = "tracing")]
+        {
+            let max_dt = transport::max_stable_dt(&self.coupling, &self.direction_scale);
+            if dt > max_dt {
+                tracing::warn!(dt, max_stable_dt = max_dt, "dt exceeds transport::max_stable_dt");
+            }
+        }
+        let held = self.frozen_rows();
+        self.step_redistribution(dt)?;
+        self.quarantine_non_finite()?;
+        self.step_transport(dt);
+        if let Some(source) = &self.source {
+            source.apply(&mut self.lattice, self.time, dt);
+        }
+        self.time += dt;
+        self.step += 1;
+        self.restore_frozen_rows(&held);
+        self.enforce_negative_policy()
+    }
+
+    /*
+    Inverse of a step(dt) with the Exact solver: undoes transport, then applies exp(-R * dt), and rewinds time/step.
+    Constraint projection and the negative policy are not inverted,
+    so forward-then-back is only the identity while those leave the state alone.
+    Errors under any solver but RedistributionSolver::Exact (the adaptive step is not exp(R * dt), so
+    exp(-R * dt) would not undo it), under TransportScheme::LinearDiffusion, which has no exact inverse,
+    and while a source is set, since SourceTerm has no inverse to undo its injection with.
+    */
+    pub fn step_back(&mut self, dt: f64) -> Result<(), &'static str> {
+        if !dt.is_finite() || dt <= 0.0 {
+            return Err("dt must be positive and finite");
+        }
+        if self.solver != RedistributionSolver::Exact {
+            return Err("step_back needs RedistributionSolver::Exact");
+        }
+        if self.transport_scheme != TransportScheme::ExactExchange {
+            return Err("step_back needs TransportScheme::ExactExchange");
+        }
+        if self.source.is_some() {
+            return Err("step_back cannot undo a source term");
+        }
+        if self.has_cross_coupling() {
+            let cross = self.free_cross_coupling();
+            transport::invert_distribute_cross_variable(&mut self.lattice, &cross, &self.direction_scale, dt);
+        }
+        let coupling = self.free_coupling();
+        transport::invert_distribute_to_neighbors(&mut self.lattice, &coupling, &self.direction_scale, dt);
+        let propagator = utils::exponential(&self.free_redistribution().a, -dt, redistribution::EXPONENTIAL_TERMS);
+        for (_, cell) in self.lattice.iter_cells_mut() {
+            redistribution::apply_propagator(cell, &propagator);
+        }
+        self.time -= dt;
+        self.step = self.step.saturating_sub(1);
+        Ok(())
+    }
+
+    /*
+    Scans for cells with a non-finite channel and applies self.nan_policy to them.
+    Abort errors only after the whole scan, so non_finite_cells lists every offender.
+    */
+    pub fn quarantine_non_finite(&mut self) -> Result<(), &'static str> {
+        self.non_finite.clear();
+        if self.nan_policy == NanPolicy::Ignore {
+            return Ok(());
+        }
+        for (coord, cell) in self.lattice.iter_cells_mut() {
+            if cell.e.iter().flatten().all(|v| v.is_finite()) {
+                continue;
+            }
+            self.non_finite.push(coord);
+            if self.nan_policy == NanPolicy::Zero {
+                cell.e = [[0.0; FORCES]; VARS];
+                #[cfg(feature = "tracing")]
+                tracing::warn!(x = coord.x, y = coord.y, z = coord.z, step = self.step, "zeroed non-finite cell");
+            }
+        }
+        if self.nan_policy == NanPolicy::Abort && !self.non_finite.is_empty() {
+            #[cfg(feature = "tracing")]
+            tracing::error!(cells = ?self.non_finite, step = self.step, "non-finite energy before transport");
+            return Err("non-finite energy before transport, see non_finite_cells");
+        }
+        Ok(())
+    }
+
+    /*
+    Cells found non-finite by the last quarantine_non_finite scan (the last step), in lattice order.
+    */
+    pub fn non_finite_cells(&self) -> &[LatticeCoord] {
+        &self.non_finite
+    }
+
+    /*
+    Applies self.negative_policy to every cell failing energy::is_valid.
+    Non-finite values are an error under every policy but Allow.
+    Clamp leaves frozen variables out (energy::clamp_negative_rows), neither zeroing them nor taking from them.
+    */
+    pub fn enforce_negative_policy(&mut self) -> Result<(), &'static str> {
+        if self.negative_policy == NegativePolicy::Allow {
+            return Ok(());
+        }
+        let free = self.frozen.map(|frozen| !frozen);
+        #[cfg(feature = "tracing")]
+        let mut clamped = 0usize;
+        for (_coord, cell) in self.lattice.iter_cells_mut() {
+            if energy::is_valid(cell, 0.0) {
+                continue;
+            }
+            if cell.e.iter().flatten().any(|v| !v.is_finite()) {
+                #[cfg(feature = "tracing")]
+                tracing::error!(x = _coord.x, y = _coord.y, z = _coord.z, "non-finite energy after step");
+                return Err("non-finite energy after step");
+            }
+            match self.negative_policy {
+                NegativePolicy::Allow => {}
+                NegativePolicy::Error => {
+                    #[cfg(feature = "tracing")]
+                    tracing::error!(x = _coord.x, y = _coord.y, z = _coord.z, "negative energy after step");
+                    return Err("negative energy after step");
+                }
+                NegativePolicy::Clamp => {
+                    if !energy::clamp_negative_rows(cell, &free) {
+                        return Err("negative energy exceeds what the cell can absorb");
+                    }
+                    #[cfg(feature = "tracing")]
+                    {
+                        clamped += 1;
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "tracing")]
+        if clamped > 0 {
+            tracing::warn!(step = self.step, cells = clamped, "clamped negative energy");
+        }
+        Ok(())
     }
 
     /*
     For each cell in lattice.iter_cells_mut():
-        redistribution::evolve_exact(cell, &self.redistribution, dt);
-        energy::project_energy(cell, &self.constraints);
+        evolve by self.solver (Exact: shared propagator; AdaptiveRk: redistribution::evolve_adaptive);
+        energy::project_energy(cell, self.constraints_at(coord));
+    Frozen variables' rows and columns of the matrix are left out (see freeze_variable).
     */
-    pub fn step_redistribution(&mut self, dt: f64) {
-        todo!();
+    pub fn step_redistribution(&mut self, dt: f64) -> Result<(), &'static str> {
+        let matrix = self.free_redistribution();
+        match self.solver {
+            RedistributionSolver::Exact => {
+                let propagator = utils::exponential(&matrix.a, dt, redistribution::EXPONENTIAL_TERMS);
+                for (coord, cell) in self.lattice.iter_cells_mut() {
+                    redistribution::apply_propagator(cell, &propagator);
+                    energy::project_energy(cell, constraints_for(&self.constraint_overrides, &self.constraints, &coord));
+                }
+            }
+            RedistributionSolver::AdaptiveRk { tol } => {
+                for (coord, cell) in self.lattice.iter_cells_mut() {
+                    redistribution::evolve_adaptive(cell, &matrix, dt, tol)?;
+                    energy::project_energy(cell, constraints_for(&self.constraint_overrides, &self.constraints, &coord));
+                }
+            }
+        }
+        Ok(())
     }
 
     /*
-    transport::distribute_to_neighbors(&mut self.lattice, &self.coupling, dt);
-    Optionally re-project for numerical safety.
+    transport::distribute_to_neighbors_with(&mut self.lattice, &self.coupling, &self.direction_scale, self.transport_scheme, dt);
+    Then transport::distribute_cross_variable when cross_coupling is non-zero.
+    Re-projects when reproject_after_transport is set.
+    Frozen variables' couplings are treated as zero.
     */
     pub fn step_transport(&mut self, dt: f64) {
-        todo!();
+        let coupling = self.free_coupling();
+        transport::distribute_to_neighbors_with(&mut self.lattice, &coupling, &self.direction_scale, self.transport_scheme, dt);
+        if self.has_cross_coupling() {
+            let cross = self.free_cross_coupling();
+            transport::distribute_cross_variable(&mut self.lattice, &cross, &self.direction_scale, dt);
+        }
+        if self.reproject_after_transport {
+            for (coord, cell) in self.lattice.iter_cells_mut() {
+                energy::project_energy(cell, constraints_for(&self.constraint_overrides, &self.constraints, &coord));
+            }
+        }
+    }
+
+    /*
+    The ConstraintSet projection uses at coord: its entry in constraint_overrides if there is one, else constraints.
+    An override replaces the global set as a whole rather than merging with it, so it must restate
+    any global constraint the cell should keep.
+    Overrides apply wherever cells are projected: after redistribution, and after transport when
+    reproject_after_transport is set. Without reprojection, transport can move a pinned cell off its
+    FixedTotal until the next step's projection.
+    */
+    pub fn constraints_at(&self, coord: LatticeCoord) -> &ConstraintSet {
+        constraints_for(&self.constraint_overrides, &self.constraints, &coord)
+    }
+
+    fn has_cross_coupling(&self) -> bool {
+        self.cross_coupling.iter().flatten().any(|&c| c != 0.0)
+    }
+
+    /*
+    self.redistribution with every row and column of a frozen variable zeroed.
+    */
+    fn free_redistribution(&self) -> RedistributionMatrix {
+        let mut matrix = self.redistribution.clone();
+        for (i, row) in matrix.a.iter_mut().enumerate() {
+            for (j, rate) in row.iter_mut().enumerate() {
+                if self.frozen[split_index(i).0] || self.frozen[split_index(j).0] {
+                    *rate = 0.0;
+                }
+            }
+        }
+        matrix
+    }
+
+    fn free_coupling(&self) -> [[f64; FORCES]; VARS] {
+        let mut coupling = self.coupling;
+        for (row, &frozen) in coupling.iter_mut().zip(self.frozen.iter()) {
+            if frozen {
+                *row = [0.0; FORCES];
+            }
+        }
+        coupling
+    }
+
+    fn free_cross_coupling(&self) -> [[f64; VARS]; VARS] {
+        let mut cross = self.cross_coupling;
+        for (i, row) in cross.iter_mut().enumerate() {
+            for (j, rate) in row.iter_mut().enumerate() {
+                if self.frozen[i] || self.frozen[j] {
+                    *rate = 0.0;
+                }
+            }
+        }
+        cross
+    }
+
+    /*
+    Per-cell copies of the frozen rows, restored by restore_frozen_rows. Empty when nothing is frozen.
+    */
+    fn frozen_rows(&self) -> Vec<[[f64; FORCES]; VARS]> {
+        if !self.frozen.contains(&true) {
+            return vec![];
+        }
+        self.lattice.cells().iter().map(|cell| cell.e).collect()
+    }
+
+    fn restore_frozen_rows(&mut self, held: &[[[f64; FORCES]; VARS]]) {
+        for ((_, cell), e) in self.lattice.iter_cells_mut().zip(held) {
+            for ((row, held_row), &frozen) in cell.e.iter_mut().zip(e.iter()).zip(self.frozen.iter()) {
+                if frozen {
+                    *row = *held_row;
+                }
+            }
+        }
     }
 
     /*
-    Loop while self.time < t_end { self.step(dt, false)?; callback(self); }
+    Loop while self.time < t_end { self.step(dt)?; callback(self); }
+    A remainder shorter than dt/2 is not stepped, so float drift in time never adds a step.
+    Records a snapshot every record_every steps when recording is on.
     */
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all, fields(t_end, dt)))]
     pub fn evolve_until(&mut self, t_end: f64, dt: f64, mut callback: impl FnMut(&Simulation)) -> Result<(), &'static str> {
-        todo!();
+        while t_end - self.time > 0.5 * dt {
+            self.step(dt)?;
+            self.after_step();
+            callback(self);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!(
+            step = self.step,
+            time = self.time,
+            conservation_error = self.verify_energy_conservation(),
+            "evolve_until reached t_end"
+        );
+        Ok(())
+    }
+
+    /*
+    Step until |Δ variance| of the total-density field over one step is below tol, or max_steps are taken.
+    Records snapshots like evolve_until.
+    */
+    pub fn evolve_until_converged(&mut self, dt: f64, tol: f64, max_steps: usize, mut callback: impl FnMut(&Simulation)) -> Result<StopReason, &'static str> {
+        let mut variance = conservation::DensityAccumulator::from_lattice(&self.lattice).variance();
+        for _ in 0..max_steps {
+            self.step(dt)?;
+            self.after_step();
+            callback(self);
+            let next = conservation::DensityAccumulator::from_lattice(&self.lattice).variance();
+            if (next - variance).abs() < tol {
+                return Ok(StopReason::Converged);
+            }
+            variance = next;
+        }
+        Ok(StopReason::MaxSteps)
+    }
+
+    fn after_step(&mut self) {
+        if self.record_every > 0 && self.step.is_multiple_of(self.record_every) {
+            self.push_snapshot();
+        }
+        for (&coord, series) in self.probes.iter_mut() {
+            if let Some(cell) = self.lattice.at(coord) {
+                series.push((self.time, cell.clone()));
+            }
+        }
+        if let Some(limit) = self.conservation_warn_threshold {
+            let error = self.verify_energy_conservation();
+            self.conservation_warning = error.is_nan() || error > limit;
+            #[cfg(feature = "tracing")]
+            if self.conservation_warning {
+                tracing::warn!(step = self.step, conservation_error = error, threshold = limit, "energy drift exceeds threshold");
+            }
+        }
+        if let Some((sums, count)) = &mut self.time_average {
+            for (sum, cell) in sums.iter_mut().zip(self.lattice.cells()) {
+                *sum += energy::total_energy(cell);
+            }
+            *count += 1;
+        }
+        if let Some(threshold) = self.halo_threshold {
+            let largest = conservation::find_peaks(&self.lattice, threshold).into_iter().next().unwrap_or_default();
+            self.largest_halo.push((self.time, largest.total_energy, largest.cells.len()));
+        }
+        if self.track_variables {
+            let budget = conservation::energy_budget(&self.lattice);
+            let residual = (budget.total - self.initial_energy) / self.initial_energy;
+            self.variable_series.push((self.time, budget.per_variable, residual));
+        }
+    }
+
+    /*
+    After every evolve_until / evolve_until_converged step, record the lattice-wide total of each variable
+    (conservation::energy_budget) and the signed residual (total − initial_energy) / initial_energy,
+    as (time, per_variable, residual). The residual is NaN or infinite when initial_energy is 0.
+    */
+    pub fn track_variable_series(&mut self) {
+        self.track_variables = true;
+    }
+
+    /*
+    The track_variable_series series, oldest first.
+    */
+    pub fn variable_series(&self) -> &[(f64, [f64; VARS], f64)] {
+        &self.variable_series
+    }
+
+    /*
+    After every evolve_until / evolve_until_converged step, record the most energetic conservation::find_peaks halo
+    at this threshold as (time, total_energy, cell count); (time, 0.0, 0) when no cell exceeds it.
+    Calling again changes the threshold and keeps the series.
+    */
+    pub fn track_largest_halo(&mut self, threshold: f64) {
+        self.halo_threshold = Some(threshold);
+    }
+
+    /*
+    The track_largest_halo series, oldest first.
+    */
+    pub fn largest_halo_series(&self) -> &[(f64, f64, usize)] {
+        &self.largest_halo
+    }
+
+    /*
+    Start (or restart, discarding earlier samples) averaging each cell's total energy over evolve steps.
+    */
+    pub fn enable_time_average(&mut self) {
+        self.time_average = Some((vec![0.0; self.lattice.cells().len()], 0));
+    }
+
+    /*
+    Mean total energy per cell, in index order, over the steps since enable_time_average.
+    Empty when averaging is off or no step has been taken yet.
+    */
+    pub fn time_averaged_field(&self) -> Vec<f64> {
+        match &self.time_average {
+            Some((sums, count)) if *count > 0 => sums.iter().map(|sum| sum / *count as f64).collect(),
+            _ => vec![],
+        }
+    }
+
+    /*
+    Sample the cell at coord after every evolve_until / evolve_until_converged step,
+    a cheap alternative to full snapshots for point diagnostics.
+    Adding a probe twice keeps its series. Errors when coord is outside the lattice.
+    */
+    pub fn add_probe(&mut self, coord: LatticeCoord) -> Result<(), &'static str> {
+        if !self.lattice.in_bounds(&coord) {
+            return Err("probe coordinate outside the lattice");
+        }
+        self.probes.entry(coord).or_default();
+        Ok(())
+    }
+
+    /*
+    Recorded (time, cell) samples per probe, oldest first. Unlike snapshots these are not capped.
+    */
+    pub fn probe_series(&self) -> &HashMap<LatticeCoord, Vec<(f64, CellState)>> {
+        &self.probes
+    }
+
+    /*
+    Whether the last evolve step's verify_energy_conservation exceeded conservation_warn_threshold,
+    for a front end to show a banner. False while no threshold is set.
+    */
+    pub fn conservation_warning(&self) -> bool {
+        self.conservation_warning
+    }
+
+    /*
+    Record a lattice snapshot every `every` steps during evolve_until; 0 turns recording off.
+    */
+    pub fn record(&mut self, every: usize) {
+        self.record_every = every;
+    }
+
+    /*
+    Recorded (time, lattice) pairs, oldest first.
+    */
+    pub fn snapshots(&self) -> &VecDeque<(f64, Lattice)> {
+        &self.snapshots
+    }
+
+    fn push_snapshot(&mut self) {
+        if self.snapshot_cap == 0 {
+            return;
+        }
+        if self.snapshots.len() >= self.snapshot_cap {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back((self.time, self.lattice.clone()));
     }
 
     /*
@@ -74,13 +735,551 @@ impl Simulation {
     Uses conservation::verify_global_conservation.
     */
     pub fn verify_energy_conservation(&self) -> f64 {
-        todo!();
+        conservation::verify_global_conservation(&self.lattice, self.initial_energy)
+    }
+
+    /*
+    conservation::verify_conservation_with_expectation at the current time.
+    */
+    pub fn verify_energy_against(&self, expected_total: impl Fn(f64) -> f64) -> f64 {
+        conservation::verify_conservation_with_expectation(&self.lattice, self.time, expected_total)
     }
 
     /*
     Calls conservation::compute_pattern_metrics.
     */
     pub fn compute_pattern_metrics(&self) -> PatternMetrics {
-        todo!();
+        conservation::compute_pattern_metrics(&self.lattice)
+    }
+}
+
+fn constraints_for<'a>(overrides: &'a HashMap<LatticeCoord, ConstraintSet>, global: &'a ConstraintSet, coord: &LatticeCoord) -> &'a ConstraintSet {
+    overrides.get(coord).unwrap_or(global)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{ConstantSource, SinusoidalDriver};
+    use crate::types::VariableConstraint;
+
+    fn simulation(size: (usize, usize, usize), redistribution: RedistributionMatrix, coupling: f64) -> Simulation {
+        let mut lattice = Lattice::new(size).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = 1.0 + coord.x as f64;
+            cell.e[1][2] = 0.5 + 0.25 * coord.y as f64;
+            cell.e[3][1] = 0.75 + 0.1 * coord.z as f64;
+        }
+        Simulation::new(lattice, redistribution, [[coupling; FORCES]; VARS], ConstraintSet::default()).unwrap()
+    }
+
+    #[test]
+    fn recording_every_n_steps_keeps_m_over_n_snapshots() {
+        let mut sim = simulation((2, 2, 2), RedistributionMatrix::default(), 0.1);
+        sim.record(3);
+        sim.evolve_until(12.0 * 0.1, 0.1, |_| {}).unwrap();
+        assert_eq!(sim.step, 12);
+        assert_eq!(sim.snapshots().len(), 4);
+        for (k, (t, _)) in sim.snapshots().iter().enumerate() {
+            assert!((t - 0.3 * (k + 1) as f64).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn full_snapshot_buffer_drops_the_oldest() {
+        let mut sim = simulation((2, 2, 2), RedistributionMatrix::default(), 0.1);
+        sim.snapshot_cap = 3;
+        sim.record(1);
+        sim.evolve_until(5.0 * 0.1, 0.1, |_| {}).unwrap();
+        let times: Vec<f64> = sim.snapshots().iter().map(|(t, _)| *t).collect();
+        assert_eq!(times.len(), 3);
+        for (t, expected) in times.iter().zip([0.3, 0.4, 0.5]) {
+            assert!((t - expected).abs() < 1e-9);
+        }
+        assert_eq!(max_difference(&sim.snapshots()[2].1, &sim.lattice), 0.0);
+    }
+
+    fn nudged_negative(policy: NegativePolicy) -> Simulation {
+        let mut sim = simulation((2, 1, 1), RedistributionMatrix::default(), 0.0);
+        sim.negative_policy = policy;
+        sim.lattice.at_mut(LatticeCoord::default()).unwrap().e[0][1] = -1e-6;
+        sim
+    }
+
+    #[test]
+    fn allow_policy_leaves_negative_values() {
+        let mut sim = nudged_negative(NegativePolicy::Allow);
+        assert!(sim.step(0.1).is_ok());
+        assert_eq!(sim.lattice.at(LatticeCoord::default()).unwrap().e[0][1], -1e-6);
+    }
+
+    #[test]
+    fn clamp_policy_zeroes_negative_values_and_conserves() {
+        let mut sim = nudged_negative(NegativePolicy::Clamp);
+        let before = energy::total_energy(sim.lattice.at(LatticeCoord::default()).unwrap());
+        assert!(sim.step(0.1).is_ok());
+        let cell = sim.lattice.at(LatticeCoord::default()).unwrap();
+        assert!(cell.e.iter().flatten().all(|&v| v >= 0.0));
+        assert!((energy::total_energy(cell) - before).abs() < 1e-12);
+    }
+
+    #[test]
+    fn error_policy_fails_the_step() {
+        let mut sim = nudged_negative(NegativePolicy::Error);
+        assert_eq!(sim.step(0.1), Err("negative energy after step"));
+    }
+
+    fn oscillating_simulation() -> Simulation {
+        let mut redistribution = RedistributionMatrix::default();
+        redistribution::set_oscillation(&mut redistribution, 0, 1, 0.7);
+        redistribution::set_oscillation(&mut redistribution, 5, 9, 0.3);
+        let mut sim = simulation((4, 4, 4), redistribution, 0.2);
+        sim.negative_policy = NegativePolicy::Allow;
+        sim
+    }
+
+    fn max_difference(a: &Lattice, b: &Lattice) -> f64 {
+        a.cells()
+            .iter()
+            .zip(b.cells())
+            .flat_map(|(a, b)| a.e.iter().flatten().zip(b.e.iter().flatten()))
+            .fold(0.0, |m: f64, (a, b)| m.max((a - b).abs()))
+    }
+
+    #[test]
+    fn fifty_steps_forward_then_back_restore_the_start() {
+        let mut sim = oscillating_simulation();
+        let start = sim.lattice.clone();
+        for _ in 0..50 {
+            sim.step(0.01).unwrap();
+        }
+        for _ in 0..50 {
+            sim.step_back(0.01).unwrap();
+        }
+        assert!(max_difference(&start, &sim.lattice) < 1e-8);
+        assert_eq!(sim.step, 0);
+        assert!(sim.time.abs() < 1e-12);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn step_warns_when_dt_exceeds_max_stable_dt() {
+        use std::sync::Mutex;
+        use tracing::{Event, Level, Subscriber};
+        use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+        struct StabilityWarnings(Arc<Mutex<usize>>);
+
+        impl<S: Subscriber> Layer<S> for StabilityWarnings {
+            fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+                if *event.metadata().level() == Level::WARN && event.fields().any(|f| f.name() == "max_stable_dt") {
+                    *self.0.lock().unwrap() += 1;
+                }
+            }
+        }
+
+        let warnings = Arc::new(Mutex::new(0));
+        let subscriber = tracing_subscriber::registry().with(StabilityWarnings(warnings.clone()));
+        let mut sim = simulation((2, 2, 2), RedistributionMatrix::default(), 0.1);
+        let max_dt = transport::max_stable_dt(&sim.coupling, &sim.direction_scale);
+        tracing::subscriber::with_default(subscriber, || {
+            sim.step(0.5 * max_dt).unwrap();
+            assert_eq!(*warnings.lock().unwrap(), 0);
+            let _ = sim.step(2.0 * max_dt);
+        });
+        assert_eq!(*warnings.lock().unwrap(), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn diffusion_only_run_converges_before_max_steps() {
+        let mut lattice = Lattice::new((6, 6, 6)).unwrap();
+        lattice.at_mut(LatticeCoord { x: 3, y: 3, z: 3 }).unwrap().e[0][0] = 50.0;
+        let mut sim = Simulation::new(lattice, RedistributionMatrix::default(), [[0.5; FORCES]; VARS], ConstraintSet::default()).unwrap();
+        let reason = sim.evolve_until_converged(0.1, 1e-9, 10_000, |_| {}).unwrap();
+        assert_eq!(reason, StopReason::Converged);
+        assert!(sim.step < 10_000);
+    }
+
+    #[test]
+    fn unconverged_run_stops_at_max_steps() {
+        let mut lattice = Lattice::new((6, 6, 6)).unwrap();
+        lattice.at_mut(LatticeCoord { x: 3, y: 3, z: 3 }).unwrap().e[0][0] = 50.0;
+        let mut sim = Simulation::new(lattice, RedistributionMatrix::default(), [[0.5; FORCES]; VARS], ConstraintSet::default()).unwrap();
+        assert_eq!(sim.evolve_until_converged(0.1, 0.0, 5, |_| {}).unwrap(), StopReason::MaxSteps);
+        assert_eq!(sim.step, 5);
+    }
+
+    #[test]
+    fn mutating_a_snapshot_leaves_the_original_alone() {
+        let mut sim = oscillating_simulation();
+        let start = sim.lattice.clone();
+        let mut branch = sim.snapshot();
+        branch.lattice.at_mut(LatticeCoord::default()).unwrap().e[0][0] = 99.0;
+        branch.coupling[0][0] = 0.0;
+        branch.step(0.01).unwrap();
+        assert_eq!(max_difference(&start, &sim.lattice), 0.0);
+        assert_eq!(sim.coupling[0][0], 0.2);
+        assert_eq!(sim.step, 0);
+        sim.step(0.01).unwrap();
+        assert_eq!(branch.step, 1);
+        assert!(branch.lattice.at(LatticeCoord::default()).unwrap().e[0][0] > 50.0);
+    }
+
+    #[test]
+    fn scale_coupling_scales_clamps_and_reports_stability() {
+        let mut sim = simulation((2, 2, 2), RedistributionMatrix::default(), 0.1);
+        let max_dt = sim.scale_coupling(2.0).unwrap();
+        assert!(sim.coupling.iter().flatten().all(|&c| (c - 0.2).abs() < 1e-15));
+        assert_eq!(max_dt, transport::max_stable_dt(&sim.coupling, &sim.direction_scale));
+        sim.scale_coupling(1e9).unwrap();
+        assert!(sim.coupling.iter().flatten().all(|&c| c == MAX_COUPLING));
+        assert!(sim.scale_coupling(-1.0).is_err());
+        assert!(sim.scale_coupling(f64::NAN).is_err());
+        sim.scale_coupling(0.0).unwrap();
+        assert!(sim.coupling.iter().flatten().all(|&c| c == 0.0));
+    }
+
+    #[test]
+    fn zero_cell_lattice_is_rejected() {
+        let result = Simulation::new(Lattice::default(), RedistributionMatrix::default(), [[0.0; FORCES]; VARS], ConstraintSet::default());
+        assert_eq!(result.err(), Some("lattice has no cells"));
+    }
+
+    #[test]
+    fn reprojection_keeps_fixed_totals_pinned_through_transport() {
+        let mut lattice = Lattice::new((4, 1, 1)).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = coord.x as f64;
+            cell.e[0][1] = 1.0;
+            cell.e[1][0] = 1.0 + coord.x as f64;
+        }
+        let mut constraints = ConstraintSet::default();
+        constraints.var_constraints[0] = VariableConstraint::FixedTotal(2.0);
+        let mut sim = Simulation::new(lattice, RedistributionMatrix::default(), [[0.3; FORCES]; VARS], constraints).unwrap();
+        sim.reproject_after_transport = true;
+        for _ in 0..30 {
+            sim.step(0.1).unwrap();
+        }
+        for cell in sim.lattice.cells() {
+            assert!((energy::per_variable(cell)[0] - 2.0).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn exact_and_adaptive_solvers_agree_on_a_non_stiff_matrix() {
+        let mut redistribution = RedistributionMatrix::default();
+        redistribution::set_oscillation(&mut redistribution, 0, 9, 1.3);
+        redistribution::set_oscillation(&mut redistribution, 9, 3, 0.4);
+        let mut exact = simulation((2, 2, 1), redistribution, 0.1);
+        exact.negative_policy = NegativePolicy::Allow;
+        let mut adaptive = exact.clone();
+        adaptive.solver = RedistributionSolver::AdaptiveRk { tol: 1e-10 };
+        for _ in 0..20 {
+            exact.step(0.1).unwrap();
+            adaptive.step(0.1).unwrap();
+        }
+        assert!(max_difference(&exact.lattice, &adaptive.lattice) < 1e-7);
+    }
+
+    #[test]
+    fn preflight_accepts_stable_and_rejects_unstable_configurations() {
+        let mut redistribution = RedistributionMatrix::default();
+        redistribution::set_oscillation(&mut redistribution, 0, 4, 2.0);
+        let sim = simulation((3, 3, 3), redistribution, 0.5);
+        assert!(sim.preflight(0.05).is_ok());
+        assert!(sim.preflight(1.0).unwrap_err().contains("transport stability limit"));
+        assert!(sim.preflight(f64::NAN).is_err());
+        assert!(sim.preflight(0.0).is_err());
+        let mut stiff = sim.clone();
+        stiff.coupling = [[0.0; FORCES]; VARS];
+        redistribution::set_oscillation(&mut stiff.redistribution, 0, 4, 1e200);
+        assert!(stiff.preflight(1.0).unwrap_err().contains("norm error"));
+    }
+
+    #[test]
+    fn cross_coupling_moves_energy_between_variables_across_cells() {
+        let mut lattice = Lattice::new((4, 1, 1)).unwrap();
+        lattice.at_mut(LatticeCoord::default()).unwrap().e[0][0] = 4.0;
+        let mut sim =
+            Simulation::new(lattice, RedistributionMatrix::default(), [[0.0; FORCES]; VARS], ConstraintSet::default())
+                .unwrap();
+        sim.cross_coupling[0][1] = 0.3;
+        sim.cross_coupling[1][0] = 0.3;
+        let start = sim.clone();
+        for _ in 0..5 {
+            sim.step(0.1).unwrap();
+        }
+        let moved: f64 = sim.lattice.cells().iter().map(|c| c.e[1][0]).sum();
+        let moved_away: f64 = sim.lattice.cells().iter().skip(1).map(|c| c.e[1][0]).sum();
+        assert!(moved > 0.1 && moved_away > 0.1);
+        assert!(sim.verify_energy_conservation() < 1e-12);
+        for _ in 0..5 {
+            sim.step_back(0.1).unwrap();
+        }
+        assert!(max_difference(&sim.lattice, &start.lattice) < 1e-9);
+    }
+
+    #[test]
+    fn step_back_refuses_linear_diffusion() {
+        let mut sim = simulation((3, 2, 2), RedistributionMatrix::default(), 0.2);
+        sim.transport_scheme = TransportScheme::LinearDiffusion;
+        sim.step(0.1).unwrap();
+        let after = sim.clone();
+        assert!(sim.step_back(0.1).is_err());
+        assert_eq!(sim.step, after.step);
+        assert_eq!(max_difference(&sim.lattice, &after.lattice), 0.0);
+    }
+
+    #[test]
+    fn step_back_refuses_the_adaptive_solver() {
+        let mut sim = oscillating_simulation();
+        sim.solver = RedistributionSolver::AdaptiveRk { tol: 1e-10 };
+        sim.step(0.01).unwrap();
+        let after = sim.clone();
+        assert_eq!(sim.step_back(0.01), Err("step_back needs RedistributionSolver::Exact"));
+        assert_eq!(sim.step, after.step);
+        assert_eq!(max_difference(&sim.lattice, &after.lattice), 0.0);
+    }
+
+    #[test]
+    fn physical_time_scales_simulation_time() {
+        let mut sim = simulation((2, 2, 2), RedistributionMatrix::default(), 0.1);
+        assert_eq!(sim.physical_time(), None);
+        sim.time_scale = Some(2.5e6);
+        for _ in 0..4 {
+            sim.step(0.5).unwrap();
+        }
+        assert_eq!(sim.physical_time(), Some(2.0 * 2.5e6));
+    }
+
+    #[test]
+    fn frozen_variable_is_held_while_others_evolve() {
+        let mut sim = simulation((4, 4, 4), RedistributionMatrix::default(), 0.1);
+        for (c, cell) in sim.lattice.iter_cells_mut() {
+            for (v, row) in cell.e.iter_mut().enumerate() {
+                for (f, value) in row.iter_mut().enumerate() {
+                    *value = 1.0 + ((c.x + 2 * c.y + 3 * c.z + v + f) % 4) as f64;
+                }
+            }
+        }
+        sim.cross_coupling[2][3] = 0.05;
+        redistribution::set_oscillation(&mut sim.redistribution, 0, 8, 0.2);
+        redistribution::set_oscillation(&mut sim.redistribution, 9, 13, 0.3);
+        sim.freeze_variable(2).unwrap();
+        assert!(sim.freeze_variable(VARS).is_err());
+        let before = sim.clone();
+        for _ in 0..5 {
+            sim.step(0.1).unwrap();
+        }
+        let rows = |sim: &Simulation, v: usize| sim.lattice.cells().iter().map(|c| c.e[v]).collect::<Vec<_>>();
+        assert_eq!(rows(&sim, 2), rows(&before, 2));
+        assert_ne!(rows(&sim, 0), rows(&before, 0));
+        let held = rows(&sim, 2);
+        sim.thaw_variable(2).unwrap();
+        sim.step(0.1).unwrap();
+        assert_ne!(rows(&sim, 2), held);
+    }
+
+    #[test]
+    fn clamp_does_not_draw_on_frozen_variables() {
+        let mut sim = nudged_negative(NegativePolicy::Clamp);
+        sim.freeze_variable(1).unwrap();
+        let frozen: Vec<_> = sim.lattice.cells().iter().map(|c| c.e[1]).collect();
+        let total = conservation::total_energy_stable(&sim.lattice);
+        sim.step(0.1).unwrap();
+        assert_eq!(sim.lattice.at(LatticeCoord::default()).unwrap().e[0][1], 0.0);
+        assert!(sim.lattice.cells().iter().map(|c| c.e[1]).eq(frozen));
+        assert!((conservation::total_energy_stable(&sim.lattice) - total).abs() < 1e-12 * total);
+    }
+
+    #[test]
+    fn probe_records_one_sample_per_step() {
+        let mut sim = simulation((3, 3, 3), RedistributionMatrix::default(), 0.1);
+        let coord = LatticeCoord { x: 1, y: 1, z: 2 };
+        sim.add_probe(coord).unwrap();
+        assert!(sim.add_probe(LatticeCoord { x: 3, y: 0, z: 0 }).is_err());
+        sim.evolve_until(1.0, 0.25, |_| {}).unwrap();
+        let series = &sim.probe_series()[&coord];
+        assert_eq!(series.len(), 4);
+        assert_eq!(series[3].0, 1.0);
+        assert_eq!(&series[3].1, sim.lattice.at(coord).unwrap());
+    }
+
+    struct Uniform(f64);
+
+    impl SourceTerm for Uniform {
+        fn apply(&self, lattice: &mut Lattice, _time: f64, dt: f64) {
+            for (_, cell) in lattice.iter_cells_mut() {
+                cell.e[0][0] += self.0 * dt;
+            }
+        }
+    }
+
+    #[test]
+    fn custom_source_injects_its_known_amount() {
+        let mut sim = simulation((2, 3, 4), RedistributionMatrix::default(), 0.1);
+        let start = conservation::total_energy_stable(&sim.lattice);
+        sim.source = Some(Arc::new(Uniform(0.5)));
+        for _ in 0..4 {
+            sim.step(0.25).unwrap();
+        }
+        let injected = conservation::total_energy_stable(&sim.lattice) - start;
+        assert!((injected - 24.0 * 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn step_back_refuses_a_source_term() {
+        let mut sim = oscillating_simulation();
+        sim.source = Some(Arc::new(ConstantSource { coord: LatticeCoord::default(), var: 0, force: 0, rate: 1.0 }));
+        sim.step(0.01).unwrap();
+        assert_eq!(sim.step_back(0.01), Err("step_back cannot undo a source term"));
+    }
+
+    #[test]
+    fn largest_halo_grows_under_a_steady_source() {
+        let mut lattice = Lattice::new((8, 8, 8)).unwrap();
+        for (_, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = 1.0;
+        }
+        let mut sim =
+            Simulation::new(lattice, RedistributionMatrix::default(), [[0.05; FORCES]; VARS], ConstraintSet::default())
+                .unwrap();
+        let coord = LatticeCoord { x: 4, y: 4, z: 4 };
+        sim.source = Some(Arc::new(ConstantSource { coord, var: 0, force: 0, rate: 5.0 }));
+        sim.track_largest_halo(1.01);
+        sim.evolve_until(3.0, 0.1, |_| {}).unwrap();
+        let series = sim.largest_halo_series();
+        assert_eq!(series.len(), 30);
+        assert!(series.windows(2).all(|w| w[1].1 > w[0].1 && w[1].2 >= w[0].2));
+    }
+
+    #[test]
+    fn crossing_the_warn_threshold_raises_the_warning() {
+        let mut sim = simulation((2, 2, 2), RedistributionMatrix::default(), 0.0);
+        let total = sim.initial_energy;
+        sim.conservation_warn_threshold = Some(0.05);
+        sim.evolve_until(0.1, 0.1, |_| {}).unwrap();
+        assert!(!sim.conservation_warning());
+        // Injects 1.0 per unit time on top of the starting total.
+        sim.source = Some(Arc::new(ConstantSource { coord: LatticeCoord::default(), var: 0, force: 0, rate: 1.0 }));
+        let crossing = 0.1 + 0.05 * total;
+        sim.evolve_until(crossing - 0.15, 0.1, |_| {}).unwrap();
+        assert!(!sim.conservation_warning());
+        sim.evolve_until(crossing + 0.15, 0.1, |_| {}).unwrap();
+        assert!(sim.conservation_warning());
+    }
+
+    #[test]
+    fn time_average_recovers_the_mean_of_an_oscillating_cell() {
+        let mut lattice = Lattice::new((2, 2, 1)).unwrap();
+        for (_, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = 5.0;
+        }
+        let mut sim =
+            Simulation::new(lattice, RedistributionMatrix::default(), [[0.0; FORCES]; VARS], ConstraintSet::default())
+                .unwrap();
+        // Driving at rate cos(t) makes the cell 5 + sin(t).
+        let coord = LatticeCoord { x: 1, y: 0, z: 0 };
+        sim.source = Some(Arc::new(SinusoidalDriver {
+            coord,
+            var: 0,
+            force: 0,
+            amplitude: 1.0,
+            angular_frequency: 1.0,
+            phase: std::f64::consts::FRAC_PI_2,
+        }));
+        assert!(sim.time_averaged_field().is_empty());
+        sim.enable_time_average();
+        assert!(sim.time_averaged_field().is_empty());
+        sim.evolve_until(20.0 * std::f64::consts::PI, std::f64::consts::PI / 200.0, |_| {}).unwrap();
+        let average = sim.time_averaged_field();
+        assert!((average[1] - 5.0).abs() < 1e-2);
+        assert_eq!(average[0], 5.0);
+    }
+
+    // Uniform exponential dilution at rate 0.3, standing in for expansion.
+    struct Dilute(f64);
+
+    impl SourceTerm for Dilute {
+        fn apply(&self, lattice: &mut Lattice, _time: f64, dt: f64) {
+            let factor = (-self.0 * dt).exp();
+            for (_, cell) in lattice.iter_cells_mut() {
+                cell.e.iter_mut().flatten().for_each(|v| *v *= factor);
+            }
+        }
+    }
+
+    #[test]
+    fn diluting_run_conserves_against_its_expected_total() {
+        let mut sim = simulation((4, 4, 4), RedistributionMatrix::default(), 0.1);
+        sim.source = Some(Arc::new(Dilute(0.3)));
+        let start = sim.initial_energy;
+        sim.evolve_until(2.0, 0.1, |_| {}).unwrap();
+        assert!(sim.verify_energy_conservation() > 0.4);
+        assert!(sim.verify_energy_against(|t| start * (-0.3 * t).exp()) < 1e-12);
+    }
+
+    #[test]
+    fn step_cost_grows_with_the_lattice_and_saturates() {
+        assert!(Simulation::estimated_step_cost((8, 8, 8)) > Simulation::estimated_step_cost((4, 4, 4)));
+        assert_eq!(Simulation::estimated_step_cost((usize::MAX, usize::MAX, usize::MAX)), u128::MAX);
+    }
+
+    #[test]
+    fn injected_nan_is_caught_with_its_coordinate() {
+        let mut sim = simulation((3, 3, 3), RedistributionMatrix::default(), 0.2);
+        let bad = LatticeCoord { x: 2, y: 0, z: 1 };
+        sim.lattice.at_mut(bad).unwrap().e[3][1] = f64::NAN;
+        let mut zeroed = sim.clone();
+        assert!(sim.step(0.1).is_err());
+        assert_eq!(sim.non_finite_cells(), &[bad]);
+        let poisoned = sim.lattice.cells().iter().filter(|c| c.e.iter().flatten().any(|v| v.is_nan())).count();
+        assert_eq!(poisoned, 1);
+
+        zeroed.nan_policy = NanPolicy::Zero;
+        zeroed.step(0.1).unwrap();
+        assert_eq!(zeroed.non_finite_cells(), &[bad]);
+        assert!(zeroed.lattice.cells().iter().all(|c| c.e.iter().flatten().all(|v| v.is_finite())));
+        zeroed.step(0.1).unwrap();
+        assert!(zeroed.non_finite_cells().is_empty());
+    }
+
+    #[test]
+    fn overridden_cell_stays_pinned_while_neighbors_evolve() {
+        let mut sim = simulation((4, 4, 4), RedistributionMatrix::default(), 0.2);
+        sim.reproject_after_transport = true;
+        let pin = LatticeCoord::default();
+        let mut pinned = ConstraintSet::default();
+        pinned.var_constraints[0] = VariableConstraint::FixedTotal(1.5);
+        sim.constraint_overrides.insert(pin, pinned);
+        let neighbor = LatticeCoord { x: 1, y: 0, z: 0 };
+        let before = sim.lattice.at(neighbor).unwrap().e;
+        for _ in 0..5 {
+            sim.step(0.1).unwrap();
+            let total: f64 = sim.lattice.at(pin).unwrap().e[0].iter().sum();
+            assert!((total - 1.5).abs() < 1e-12);
+        }
+        assert_ne!(sim.lattice.at(neighbor).unwrap().e, before);
+    }
+
+    #[test]
+    fn variable_series_records_every_step() {
+        let mut matrix = RedistributionMatrix::default();
+        redistribution::set_oscillation(&mut matrix, 0, 12, 0.3);
+        let mut sim = simulation((3, 3, 3), matrix, 0.1);
+        sim.negative_policy = NegativePolicy::Allow;
+        sim.evolve_until(0.2, 0.1, |_| {}).unwrap();
+        assert!(sim.variable_series().is_empty());
+        sim.track_variable_series();
+        sim.evolve_until(1.2, 0.1, |_| {}).unwrap();
+        let series = sim.variable_series();
+        assert_eq!(series.len(), sim.step - 2);
+        assert!(series.windows(2).all(|w| w[1].0 > w[0].0));
+        let (time, per_variable, residual) = series.last().unwrap();
+        assert!((time - sim.time).abs() < 1e-12);
+        assert!((residual.abs() - sim.verify_energy_conservation()).abs() < 1e-12);
+        assert!((per_variable.iter().sum::<f64>() - (1.0 + residual) * sim.initial_energy).abs() < 1e-9);
+        assert_ne!(series[0].1[0], per_variable[0]);
+        assert_ne!(series[0].1[3], per_variable[3]);
+    }
+}
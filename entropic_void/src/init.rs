@@ -8,9 +8,15 @@ Uses energy::project_energy.
 Uses utils::sampling.
 */
 
-use rand::rngs::SmallRng;
+use crate::energy;
 use crate::lattice::Lattice;
 use crate::types::{CellState, ConstraintSet, SpatialMode, FORCES, VARS};
+use crate::utils::{self, SimRng};
+
+/*
+Allowed |sum - 1| for percentage rows in EnergyDistribution::validate.
+*/
+pub const DISTRIBUTION_TOLERANCE: f64 = 1e-9;
 
 /**/
 #[derive(Default)]
@@ -24,9 +30,60 @@ pub struct EnergyDistribution {
 impl EnergyDistribution {
     /*
     Allocates energies according to percentages.
+    e[i][f] = total * var_pct[i] * force_pct[i][f]; only sums to total if validate passes.
     */
     pub fn to_cell(&self) -> CellState {
-        todo!();
+        let mut cell = CellState::default();
+        for (i, row) in cell.e.iter_mut().enumerate() {
+            for (f, value) in row.iter_mut().enumerate() {
+                *value = self.total * self.var_pct[i] * self.force_pct[i][f];
+            }
+        }
+        cell
+    }
+
+    /*
+    Like to_cell, but rescales var_pct and each force_pct row to sum to 1 first,
+    so the cell always holds exactly total. All-zero rows become an even split.
+    */
+    pub fn to_cell_normalized(&self) -> CellState {
+        let normalized = EnergyDistribution {
+            total: self.total,
+            var_pct: normalize(&self.var_pct),
+            force_pct: self.force_pct.map(|row| normalize(&row)),
+        };
+        normalized.to_cell()
+    }
+
+    /*
+    Checks var_pct and every force_pct row are finite, non-negative and sum to 1 ± DISTRIBUTION_TOLERANCE.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        check_row("var_pct", &self.var_pct)?;
+        for (i, row) in self.force_pct.iter().enumerate() {
+            check_row(&format!("force_pct[{i}]"), row)?;
+        }
+        Ok(())
+    }
+}
+
+fn check_row(name: &str, row: &[f64]) -> Result<(), String> {
+    if let Some(bad) = row.iter().find(|p| !p.is_finite() || **p < 0.0) {
+        return Err(format!("{name} contains invalid percentage {bad}"));
+    }
+    let sum: f64 = row.iter().sum();
+    if (sum - 1.0).abs() > DISTRIBUTION_TOLERANCE {
+        return Err(format!("{name} sums to {sum}, expected 1"));
+    }
+    Ok(())
+}
+
+fn normalize<const N: usize>(row: &[f64; N]) -> [f64; N] {
+    let sum: f64 = row.iter().sum();
+    if sum > 0.0 {
+        row.map(|p| p / sum)
+    } else {
+        [1.0 / N as f64; N]
     }
 }
 
@@ -36,6 +93,7 @@ Compute local energy E_cell = base_energy * (1 + small_noise).
 Generate CellState from distribution.
 Scale to E_cell.
 energy::project_energy(cell, constraints).
+Errors on a zero-cell lattice or a distribution with no energy to scale.
 */
 pub fn initialize_homogeneous(
     lattice: &mut Lattice,
@@ -43,26 +101,108 @@ pub fn initialize_homogeneous(
     noise_fraction: f64,
     distribution: &EnergyDistribution,
     constraints: &ConstraintSet,
-) {
-    todo!();
+    rng: &mut SimRng,
+) -> Result<(), &'static str> {
+    if lattice.cells().is_empty() {
+        return Err("lattice has no cells");
+    }
+    let template = distribution.to_cell_normalized();
+    let template_total = energy::total_energy(&template);
+    if !template_total.is_finite() || template_total <= 0.0 {
+        return Err("energy distribution has no energy to scale");
+    }
+    for (_, cell) in lattice.iter_cells_mut() {
+        let e_cell = utils::add_noise(base_energy, noise_fraction, rng);
+        let factor = e_cell / template_total;
+        for (row, source) in cell.e.iter_mut().zip(template.e.iter()) {
+            for (value, &t) in row.iter_mut().zip(source.iter()) {
+                *value = t * factor;
+            }
+        }
+        energy::project_energy(cell, constraints);
+    }
+    Ok(())
 }
 
 /*
 Use cos(k·r + φ) shape to modulate energies.
+Each cell's total becomes base_energy * (1 + mode.amplitude * cos(2π (k_x x/N_x + k_y y/N_y + k_z z/N_z))),
+split over channels in the cell's current proportions (evenly if it is empty); φ = 0.
+An amplitude above 1 gives negative cells. mode.frequency is not used.
+A lattice samples each axis at N points, so integer k and k + N give the same field:
+a component beyond the Nyquist limit |k| <= N/2 would silently alias onto a lower mode,
+and the FFT would peak somewhere other than mode.k. Such modes are rejected instead.
 */
-pub fn initialize_structured(lattice: &mut Lattice, mode: &SpatialMode, base_energy: f64) {
-    todo!();
+pub fn initialize_structured(lattice: &mut Lattice, mode: &SpatialMode, base_energy: f64) -> Result<(), String> {
+    let (sx, sy, sz) = lattice.size();
+    for (axis, k, n) in [("x", mode.k.0, sx), ("y", mode.k.1, sy), ("z", mode.k.2, sz)] {
+        if k.unsigned_abs() > n / 2 {
+            return Err(format!("k_{axis} = {k} exceeds the Nyquist limit {} of a {n}-cell axis", n / 2));
+        }
+    }
+    if lattice.cells().is_empty() {
+        return Err("lattice has no cells".to_string());
+    }
+    let (kx, ky, kz) = (mode.k.0 as f64, mode.k.1 as f64, mode.k.2 as f64);
+    for (coord, cell) in lattice.iter_cells_mut() {
+        let phase = std::f64::consts::TAU
+            * (kx * coord.x as f64 / sx as f64 + ky * coord.y as f64 / sy as f64 + kz * coord.z as f64 / sz as f64);
+        let e_cell = base_energy * (1.0 + mode.amplitude * phase.cos());
+        let current = energy::total_energy(cell);
+        for value in cell.e.iter_mut().flatten() {
+            *value = if current != 0.0 { *value * e_cell / current } else { e_cell / (VARS * FORCES) as f64 };
+        }
+    }
+    Ok(())
 }
 
 /*
 Returns [p_0..p_{n-1}] with sum 1.
 */
-pub fn sample_simplex(n: usize, rng: &mut SmallRng) -> Vec<f64> {
+pub fn sample_simplex(n: usize, rng: &mut SimRng) -> Vec<f64> {
     todo!();
 }
 
 /**/
-pub fn random_energy_distribution(total: f64, rng: &mut SmallRng) -> EnergyDistribution {
+pub fn random_energy_distribution(total: f64, rng: &mut SimRng) -> EnergyDistribution {
     todo!();
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn even(total: f64) -> EnergyDistribution {
+        EnergyDistribution { total, var_pct: [0.2; VARS], force_pct: [[0.25; FORCES]; VARS] }
+    }
+
+    #[test]
+    fn valid_distribution_passes_and_fills_total() {
+        let distribution = even(10.0);
+        assert!(distribution.validate().is_ok());
+        assert!((energy::total_energy(&distribution.to_cell()) - 10.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn mis_normalized_distribution_is_rejected_but_normalizes() {
+        let mut distribution = even(10.0);
+        distribution.var_pct[0] = 0.5;
+        assert!(distribution.validate().unwrap_err().starts_with("var_pct sums to 1.29"));
+        assert!((energy::total_energy(&distribution.to_cell_normalized()) - 10.0).abs() < 1e-12);
+        distribution.var_pct = [0.2; VARS];
+        distribution.force_pct[2][1] = -0.25;
+        assert!(distribution.validate().unwrap_err().starts_with("force_pct[2]"));
+    }
+
+    #[test]
+    fn over_nyquist_modes_are_rejected() {
+        let mut lattice = Lattice::new((8, 6, 5)).unwrap();
+        let mode = |k| SpatialMode { k, amplitude: 0.5, frequency: 0.0 };
+        assert!(initialize_structured(&mut lattice, &mode((5, 0, 0)), 1.0).unwrap_err().starts_with("k_x = 5"));
+        assert!(initialize_structured(&mut lattice, &mode((0, 0, -3)), 1.0).unwrap_err().starts_with("k_z = -3"));
+        assert!(lattice.cells().iter().all(|cell| energy::total_energy(cell) == 0.0));
+        initialize_structured(&mut lattice, &mode((4, -3, 2)), 1.0).unwrap();
+        let total: f64 = lattice.cells().iter().map(energy::total_energy).sum();
+        assert!((total - lattice.cells().len() as f64).abs() < 1e-9);
+    }
+}
@@ -10,7 +10,7 @@ Called by: init, transport, evolution, visualization, conservation, oscillation.
 use crate::types::{CellState, FORCES, LatticeCoord, VARS};
 
 /**/
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Lattice {
     size: (usize, usize, usize),
     cells: Vec<CellState>,
@@ -21,11 +21,15 @@ impl Lattice {
     /*
     Create empty lattice.
     All energy is 0.0
+    None if any dimension is 0 or the cells could not be addressed in memory.
     */
     pub fn new(size: (usize, usize, usize)) -> Option<Lattice> {
-        let cell_count = Self::cell_count_size(size);
-        let mut cells: Vec<CellState> = vec![];
-        for _ in 0..cell_count? {
+        let cell_count = Self::cell_count_size(size)?;
+        if cell_count == 0 || Self::estimated_bytes(size) > isize::MAX as u128 {
+            return None;
+        }
+        let mut cells: Vec<CellState> = Vec::with_capacity(cell_count as usize);
+        for _ in 0..cell_count {
             // Double check 0 or 1 start
             let cell_state = CellState {
                 e: [[0.0; FORCES]; VARS],
@@ -42,6 +46,13 @@ impl Lattice {
         self.size
     }
 
+    /*
+    Cells in index order.
+    */
+    pub fn cells(&self) -> &[CellState] {
+        &self.cells
+    }
+
     /**/
     pub fn cell_count_size(size: (usize, usize, usize)) -> Option<u128> {
         let cell_count = (size.0 as u128)
@@ -50,28 +61,40 @@ impl Lattice {
         Some(cell_count)
     }
 
+    /*
+    Bytes the cells of a lattice this size would take: size_of::<CellState>() per cell
+    (160 with 5 variables × 4 forces of f64, so 512³ is ~21 GB). Saturates at u128::MAX.
+    */
+    pub fn estimated_bytes(size: (usize, usize, usize)) -> u128 {
+        Self::cell_count_size(size)
+            .and_then(|count| count.checked_mul(size_of::<CellState>() as u128))
+            .unwrap_or(u128::MAX)
+    }
+
     /*
     Convert coordinates → index.
+    Row-major: x fastest, then y, then z.
     */
     pub fn index(&self, coord: LatticeCoord) -> Option<u128> {
-        let cell_count = Self::cell_count_size(self.size);
-        let layer = cell_count?
-            .checked_mul(cell_count?)?
-            .checked_mul(coord.z as u128)?;
-        let row = cell_count?.checked_mul(coord.y as u128)?;
+        let (sx, sy, _) = self.size;
+        let row = (coord.y as u128).checked_mul(sx as u128)?;
+        let layer = (coord.z as u128)
+            .checked_mul(sx as u128)?
+            .checked_mul(sy as u128)?;
         (coord.x as u128).checked_add(row)?.checked_add(layer)
     }
 
     /*
     Convert index → coordinates.
+    Exact inverse of index.
     */
     pub fn coord(&self, index: u128) -> Option<LatticeCoord> {
-        let n = Self::cell_count_size(self.size)?;
-        let n2 = n.checked_mul(n)?;
-        let x = index.checked_div(n2)?;
-        let r = index.checked_rem(n2)?;
-        let y = r.checked_div(n)?;
-        let z = r.checked_rem(n)?;
+        let (sx, sy, _) = self.size;
+        let layer = (sx as u128).checked_mul(sy as u128)?;
+        let z = index.checked_div(layer)?;
+        let r = index.checked_rem(layer)?;
+        let y = r.checked_div(sx as u128)?;
+        let x = r.checked_rem(sx as u128)?;
         Some(LatticeCoord {
             x: x as usize,
             y: y as usize,
@@ -80,17 +103,25 @@ impl Lattice {
     }
 
     /*
-    TODO
+    Cell at coord, None if out of bounds.
     */
     pub fn at(&self, coord: LatticeCoord) -> Option<&CellState> {
-        todo!();
+        if !self.in_bounds(&coord) {
+            return None;
+        }
+        let index = self.index(coord)? as usize;
+        self.cells.get(index)
     }
 
     /*
-    TODO
+    Mutable cell at coord, None if out of bounds.
     */
     pub fn at_mut(&mut self, coord: LatticeCoord) -> Option<&mut CellState> {
-        todo!();
+        if !self.in_bounds(&coord) {
+            return None;
+        }
+        let index = self.index(coord)? as usize;
+        self.cells.get_mut(index)
     }
 
     /**/
@@ -99,11 +130,51 @@ impl Lattice {
     }
 
     /*
-    TODO
     Apply periodic boundary conditions.
     */
     pub fn periodic_coord(&self, coord: LatticeCoord) -> LatticeCoord {
-        todo!();
+        LatticeCoord {
+            x: coord.x % self.size.0.max(1),
+            y: coord.y % self.size.1.max(1),
+            z: coord.z % self.size.2.max(1),
+        }
+    }
+
+    /*
+    Step from coord by offset, wrapping around periodic boundaries.
+    */
+    pub fn shifted(&self, coord: &LatticeCoord, offset: (isize, isize, isize)) -> LatticeCoord {
+        let wrap = |c: usize, d: isize, n: usize| -> usize {
+            let n = n.max(1) as isize;
+            (c as isize + d).rem_euclid(n) as usize
+        };
+        LatticeCoord {
+            x: wrap(coord.x, offset.0, self.size.0),
+            y: wrap(coord.y, offset.1, self.size.1),
+            z: wrap(coord.z, offset.2, self.size.2),
+        }
+    }
+
+    /*
+    Two distinct cells borrowed mutably at once.
+    None if either is out of bounds or both are the same cell.
+    */
+    pub fn pair_mut(&mut self, a: LatticeCoord, b: LatticeCoord) -> Option<(&mut CellState, &mut CellState)> {
+        if !self.in_bounds(&a) || !self.in_bounds(&b) {
+            return None;
+        }
+        let ia = self.index(a)? as usize;
+        let ib = self.index(b)? as usize;
+        if ia == ib {
+            return None;
+        }
+        if ia < ib {
+            let (lo, hi) = self.cells.split_at_mut(ib);
+            Some((&mut lo[ia], &mut hi[0]))
+        } else {
+            let (lo, hi) = self.cells.split_at_mut(ia);
+            Some((&mut hi[0], &mut lo[ib]))
+        }
     }
 
     /*
@@ -124,26 +195,211 @@ impl Lattice {
     }
 
     /*
-    TODO
+    The distinct cells of the periodic 3×3×3 block around coord, excluding coord itself.
+    Fewer than 26 when a dimension is below 3 and offsets wrap onto the same cell.
+    Empty if coord is out of bounds.
     */
     pub fn neighbors_26(&self, coord: LatticeCoord) -> Vec<LatticeCoord> {
-        todo!();
+        let mut neighbors: Vec<LatticeCoord> = vec![];
+        if !self.in_bounds(&coord) {
+            return neighbors;
+        }
+        for dz in -1..=1 {
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let n = self.shifted(&coord, (dx, dy, dz));
+                    if n != coord && !neighbors.contains(&n) {
+                        neighbors.push(n);
+                    }
+                }
+            }
+        }
+        neighbors
     }
 
     /*
-    TODO
-    *//*
-    pub fn iter_cells(&self) -> impl Iterator<Item = (LatticeCoord, &CellState)> {
-        todo!();
+    Merge each factor³ block into one coarse cell holding the block's summed energy per channel,
+    so total energy is preserved.
+    None if factor is 0 or does not divide every dimension.
+    */
+    pub fn coarse_grain(&self, factor: usize) -> Option<Lattice> {
+        let (sx, sy, sz) = self.size;
+        if factor == 0 || !sx.is_multiple_of(factor) || !sy.is_multiple_of(factor) || !sz.is_multiple_of(factor) {
+            return None;
+        }
+        let mut coarse = Lattice::new((sx / factor, sy / factor, sz / factor))?;
+        for (coord, cell) in self.iter_cells() {
+            let target = LatticeCoord {
+                x: coord.x / factor,
+                y: coord.y / factor,
+                z: coord.z / factor,
+            };
+            let block = coarse.at_mut(target)?;
+            for (row, source) in block.e.iter_mut().zip(cell.e.iter()) {
+                for (value, add) in row.iter_mut().zip(source.iter()) {
+                    *value += add;
+                }
+            }
+        }
+        Some(coarse)
     }
+
+    /*
+    All cells in index order with their coordinates.
     */
+    pub fn iter_cells(&self) -> impl Iterator<Item = (LatticeCoord, &CellState)> {
+        let (sx, sy, _) = self.size;
+        self.cells.iter().enumerate().map(move |(i, cell)| (Self::unflatten(i, sx, sy), cell))
+    }
 
     /*
-    TODO
-    *//*
+    Mutable counterpart of iter_cells.
+    */
     pub fn iter_cells_mut(&mut self) -> impl Iterator<Item = (LatticeCoord, &mut CellState)> {
-        todo!();
+        let (sx, sy, _) = self.size;
+        self.cells.iter_mut().enumerate().map(move |(i, cell)| (Self::unflatten(i, sx, sy), cell))
     }
+
+    /*
+    64-bit FNV-1a over the size and then every channel's f64 bit pattern (little-endian), cells in index order.
+    The same on every platform and build, so golden tests can compare it instead of whole lattices.
+    For regression detection only: it is not cryptographic and collisions can be constructed.
+    Exact bits count, so 0.0 and -0.0 (or differently-rounded results) hash differently.
     */
+    pub fn stable_hash(&self) -> u64 {
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+        let (sx, sy, sz) = self.size;
+        let sizes = [sx, sy, sz].map(|n| n as u64);
+        let values = self.cells.iter().flat_map(|cell| cell.e.iter().flatten().map(|v| v.to_bits()));
+        sizes
+            .into_iter()
+            .chain(values)
+            .flat_map(u64::to_le_bytes)
+            .fold(OFFSET, |hash, byte| (hash ^ byte as u64).wrapping_mul(PRIME))
+    }
+
+    fn unflatten(i: usize, sx: usize, sy: usize) -> LatticeCoord {
+        LatticeCoord {
+            x: i % sx,
+            y: (i / sx) % sy,
+            z: i / (sx * sy),
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::energy;
+
+    fn coords(size: (usize, usize, usize)) -> impl Iterator<Item = LatticeCoord> {
+        (0..size.2).flat_map(move |z| (0..size.1).flat_map(move |y| (0..size.0).map(move |x| LatticeCoord { x, y, z })))
+    }
+
+    #[test]
+    fn index_uses_per_axis_strides() {
+        let lattice = Lattice::new((3, 5, 7)).unwrap();
+        assert_eq!(lattice.index(LatticeCoord { x: 2, y: 4, z: 6 }), Some(2 + 4 * 3 + 6 * 15));
+        let cube = Lattice::new((8, 8, 8)).unwrap();
+        assert_eq!(cube.index(LatticeCoord { x: 1, y: 2, z: 3 }), Some(1 + 2 * 8 + 3 * 64));
+    }
+
+    #[test]
+    fn coord_inverts_index() {
+        for size in [(3, 5, 7), (1, 4, 2), (6, 1, 3), (8, 8, 8)] {
+            let lattice = Lattice::new(size).unwrap();
+            for (expected, coord) in coords(size).enumerate() {
+                let index = lattice.index(coord).unwrap();
+                assert_eq!(index, expected as u128);
+                assert_eq!(lattice.coord(index), Some(coord));
+            }
+        }
+    }
+
+    #[test]
+    fn at_mut_writes_are_read_back_by_at() {
+        let mut lattice = Lattice::new((3, 5, 7)).unwrap();
+        for coord in coords((3, 5, 7)) {
+            if let Some(cell) = lattice.at_mut(coord) {
+                cell.e[coord.x % VARS][coord.y % FORCES] = (coord.x + 10 * coord.y + 100 * coord.z) as f64;
+            }
+        }
+        for coord in coords((3, 5, 7)) {
+            let cell = lattice.at(coord).unwrap();
+            assert_eq!(cell.e[coord.x % VARS][coord.y % FORCES], (coord.x + 10 * coord.y + 100 * coord.z) as f64);
+        }
+        assert_eq!(lattice.cells[2 + 4 * 3 + 6 * 15].e[2][0], 642.0);
+    }
+
+    #[test]
+    fn out_of_bounds_access_is_none() {
+        let mut lattice = Lattice::new((3, 5, 7)).unwrap();
+        for coord in [LatticeCoord { x: 3, y: 0, z: 0 }, LatticeCoord { x: 0, y: 5, z: 0 }, LatticeCoord { x: 0, y: 0, z: 7 }] {
+            assert!(lattice.at(coord).is_none());
+            assert!(lattice.at_mut(coord).is_none());
+        }
+    }
+
+    #[test]
+    fn coarse_grain_of_uniform_lattice_preserves_total() {
+        let mut lattice = Lattice::new((4, 6, 2)).unwrap();
+        for (_, cell) in lattice.iter_cells_mut() {
+            cell.e[2][3] = 1.5;
+        }
+        let coarse = lattice.coarse_grain(2).unwrap();
+        assert_eq!(coarse.size(), (2, 3, 1));
+        let total: f64 = coarse.cells().iter().map(energy::total_energy).sum();
+        assert!((total - 1.5 * 48.0).abs() < 1e-12);
+        assert!(coarse.cells().iter().all(|cell| cell.e[2][3] == 1.5 * 8.0));
+    }
+
+    #[test]
+    fn coarse_grain_rejects_non_dividing_factors() {
+        let lattice = Lattice::new((4, 6, 2)).unwrap();
+        assert!(lattice.coarse_grain(4).is_none());
+        assert!(lattice.coarse_grain(0).is_none());
+    }
+
+    #[test]
+    fn degenerate_sizes_are_rejected() {
+        assert!(Lattice::new((0, 4, 4)).is_none());
+        assert!(Lattice::new((4, 4, 0)).is_none());
+        assert_eq!(Lattice::cell_count_size((usize::MAX, usize::MAX, usize::MAX)), None);
+        assert!(Lattice::new((usize::MAX, usize::MAX, usize::MAX)).is_none());
+        assert!(Lattice::new((usize::MAX, 2, 1)).is_none());
+    }
+
+    #[test]
+    fn neighbors_26_deduplicates_on_small_axes() {
+        let origin = LatticeCoord::default();
+        assert_eq!(Lattice::new((3, 3, 3)).unwrap().neighbors_26(origin).len(), 26);
+        assert_eq!(Lattice::new((2, 1, 1)).unwrap().neighbors_26(origin).len(), 1);
+        assert_eq!(Lattice::new((2, 2, 1)).unwrap().neighbors_26(origin).len(), 3);
+    }
 
+    #[test]
+    fn byte_estimate_is_cell_size_times_cell_count() {
+        assert_eq!(Lattice::estimated_bytes((4, 5, 6)), (size_of::<CellState>() * 120) as u128);
+        assert_eq!(Lattice::estimated_bytes((512, 512, 512)), 160 * 512u128.pow(3));
+        assert_eq!(Lattice::estimated_bytes((usize::MAX, usize::MAX, usize::MAX)), u128::MAX);
+    }
+
+    #[test]
+    fn stable_hash_tracks_exact_contents_and_shape() {
+        let mut a = Lattice::new((3, 2, 2)).unwrap();
+        for (coord, cell) in a.iter_cells_mut() {
+            cell.e[1][2] = coord.x as f64 * 0.1;
+        }
+        let hash = a.stable_hash();
+        assert_eq!(a.clone().stable_hash(), hash);
+        a.at_mut(LatticeCoord { x: 2, y: 1, z: 1 }).unwrap().e[4][3] += 1e-16;
+        assert_ne!(a.stable_hash(), hash);
+        assert_ne!(Lattice::new((2, 3, 2)).unwrap().stable_hash(), Lattice::new((3, 2, 2)).unwrap().stable_hash());
+        let mut signed = Lattice::new((1, 1, 1)).unwrap();
+        assert_eq!(signed.stable_hash(), 0x1f94_2a88_891f_f0c4);
+        signed.at_mut(LatticeCoord::default()).unwrap().e[0][0] = -0.0;
+        assert_ne!(signed.stable_hash(), 0x1f94_2a88_891f_f0c4);
+    }
 }
@@ -7,8 +7,11 @@ Uses CellState, RedistributionMatrix, OscillationMode, SpatialMode from types.
 Uses redistribution, lattice, transport.
 Uses utils::fft, utils::hilbert.
 */
+use crate::energy;
 use crate::lattice::Lattice;
+use crate::transport;
 use crate::types::{CellState, OscillationMode, RedistributionMatrix, SpatialMode};
+use crate::utils;
 
 /**/
 #[derive(Default)]
@@ -37,7 +40,33 @@ Flatten cell.e to vector E.
 Compute dot A = E · mode.eigenvector.
 */
 pub fn project_onto_mode(cell: &CellState, mode: &OscillationMode) -> f64 {
-    todo!();
+    cell.flatten().iter().zip(mode.eigenvector.iter()).map(|(e, v)| e * v).sum()
+}
+
+/*
+Amplitude of cell on each mode: E · v / (v · v), so a cell equal to c · v reads c on that mode.
+This is the unique decomposition when the eigenvectors are mutually orthogonal, as for an
+antisymmetric matrix; otherwise each entry is that mode's own projection and the entries overlap.
+Modes with a zero eigenvector read 0.0.
+*/
+pub fn mode_decomposition(cell: &CellState, modes: &[OscillationMode]) -> Vec<f64> {
+    modes.iter().map(|mode| mode_amplitude(cell, mode)).collect()
+}
+
+/*
+mode_decomposition's entry for modes[mode_idx] at every cell, in index order.
+Empty if mode_idx is out of range.
+*/
+pub fn mode_amplitude_field(lattice: &Lattice, modes: &[OscillationMode], mode_idx: usize) -> Vec<f64> {
+    let Some(mode) = modes.get(mode_idx) else {
+        return vec![];
+    };
+    lattice.iter_cells().map(|(_, cell)| mode_amplitude(cell, mode)).collect()
+}
+
+fn mode_amplitude(cell: &CellState, mode: &OscillationMode) -> f64 {
+    let norm: f64 = mode.eigenvector.iter().map(|v| v * v).sum();
+    if norm > 0.0 { project_onto_mode(cell, mode) / norm } else { 0.0 }
 }
 
 /*
@@ -50,17 +79,170 @@ pub fn track_mode(tracker: &mut ModeTracker, cell: &CellState, t: f64) {
 
 /*
 Uses utils::fft or utils::hilbert::instantaneous_phase to recover dominant frequency.
+Dominant frequency (cycles per unit time) of the recorded amplitudes, assumed uniformly sampled,
+with the sample spacing taken from the first and last times.
+The mean is removed and window applied before the FFT; the estimate is the power-weighted centroid
+of the strongest non-DC bin and its two neighbours, which a tapering window keeps free of leakage.
+None for fewer than 4 samples, a non-increasing time column, or a flat record.
 */
-pub fn extract_frequency_from_timeseries(history: &[(f64, f64)]) -> Option<f64> {
-    todo!();
+pub fn extract_frequency_from_timeseries(history: &[(f64, f64)], window: utils::Window) -> Option<f64> {
+    let n = history.len();
+    let (power, dt) = windowed_power(history, window, n)?;
+    let (peak, half) = spectral_peak(&power)?;
+    let bins = (peak - 1).max(1)..=(peak + 1).min(half);
+    let total: f64 = power[bins.clone()].iter().sum();
+    let centroid = bins.map(|k| k as f64 * power[k]).sum::<f64>() / total;
+    Some(centroid / (n as f64 * dt))
+}
+
+/*
+Like extract_frequency_from_timeseries, but zero-pads the windowed record to
+n.next_power_of_two() · pad_factor samples (pad_factor 0 counts as 1) and fits a parabola
+through the log power of the peak bin and its neighbours, returning the vertex.
+Padding interpolates the spectrum; it does not add resolution the record lacks.
+*/
+pub fn extract_frequency_interpolated(history: &[(f64, f64)], window: utils::Window, pad_factor: usize) -> Option<f64> {
+    let len = history.len().next_power_of_two() * pad_factor.max(1);
+    let (power, dt) = windowed_power(history, window, len)?;
+    let (peak, half) = spectral_peak(&power)?;
+    let mut offset = 0.0;
+    if peak > 1 && peak < half && power[peak - 1] > 0.0 && power[peak + 1] > 0.0 {
+        let (a, b, c) = (power[peak - 1].ln(), power[peak].ln(), power[peak + 1].ln());
+        let denom = a - 2.0 * b + c;
+        if denom < 0.0 {
+            offset = (0.5 * (a - c) / denom).clamp(-0.5, 0.5);
+        }
+    }
+    Some((peak as f64 + offset) / (len as f64 * dt))
+}
+
+/*
+Power spectrum of the mean-removed, windowed amplitudes zero-padded to len, and the sample spacing.
+*/
+fn windowed_power(history: &[(f64, f64)], window: utils::Window, len: usize) -> Option<(Vec<f64>, f64)> {
+    let n = history.len();
+    if n < 4 {
+        return None;
+    }
+    let dt = (history[n - 1].0 - history[0].0) / (n - 1) as f64;
+    if dt.is_nan() || dt <= 0.0 {
+        return None;
+    }
+    let mean = history.iter().map(|&(_, a)| a).sum::<f64>() / n as f64;
+    let mut signal: Vec<f64> = history
+        .iter()
+        .zip(window.coefficients(n))
+        .map(|(&(_, a), w)| (a - mean) * w)
+        .collect();
+    signal.resize(len.max(n), 0.0);
+    Some((utils::power_spectrum(&utils::fft_1d(&signal)), dt))
+}
+
+/*
+Strongest bin in 1..=len/2 and len/2, or None for a flat spectrum.
+*/
+fn spectral_peak(power: &[f64]) -> Option<(usize, usize)> {
+    let half = power.len() / 2;
+    let (peak, &peak_power) = power[1..=half]
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(k, p)| (k + 1, p))?;
+    if peak_power <= 0.0 {
+        return None;
+    }
+    Some((peak, half))
+}
+
+/*
+Envelope |x + i·H[x]| of the recorded amplitudes.
+Assumes history is uniformly sampled in time; the time column is ignored.
+Values near the ends of the record are less reliable since the transform treats it as periodic.
+*/
+pub fn amplitude_envelope(history: &[(f64, f64)]) -> Vec<f64> {
+    let signal: Vec<f64> = history.iter().map(|&(_, a)| a).collect();
+    utils::analytic_signal(&signal).iter().map(|c| c.norm()).collect()
+}
+
+/*
+Shannon entropy of the normalized one-sided power spectrum, divided by ln(bins) to land in [0, 1].
+The mean is removed first so DC does not count as a tone.
+≈ 0 for a single clean oscillation, → 1 for white noise.
+Returns 0.0 for records too short or flat to have a spectrum.
+*/
+pub fn spectral_entropy(history: &[(f64, f64)]) -> f64 {
+    let n = history.len();
+    if n < 4 {
+        return 0.0;
+    }
+    let mean = history.iter().map(|&(_, a)| a).sum::<f64>() / n as f64;
+    let signal: Vec<f64> = history.iter().map(|&(_, a)| a - mean).collect();
+    let power = utils::power_spectrum(&utils::fft_1d(&signal));
+    let one_sided = &power[1..=n / 2];
+    let total: f64 = one_sided.iter().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    let entropy: f64 = one_sided
+        .iter()
+        .map(|&p| p / total)
+        .filter(|&p| p > 0.0)
+        .map(|p| -p * p.ln())
+        .sum();
+    entropy / (one_sided.len() as f64).ln()
+}
+
+/*
+Frequency (cycles per unit time) of the strongest non-DC bin of a uniformly sampled signal.
+0.0 for signals that are too short or flat.
+*/
+fn dominant_bin_frequency(signal: &[f64], dt: f64) -> f64 {
+    let n = signal.len();
+    if n < 2 || dt <= 0.0 {
+        return 0.0;
+    }
+    let mean = signal.iter().sum::<f64>() / n as f64;
+    let centered: Vec<f64> = signal.iter().map(|&x| x - mean).collect();
+    let power = utils::power_spectrum(&utils::fft_1d(&centered));
+    let mut best = (0, 0.0);
+    for (k, &p) in power.iter().enumerate().take(n / 2 + 1).skip(1) {
+        if p > best.1 {
+            best = (k, p);
+        }
+    }
+    best.0 as f64 / (n as f64 * dt)
+}
+
+/*
+Per-cell dominant frequency of total energy across snapshots taken every dt, in index order.
+Every snapshot must share the first one's size; otherwise, or with no snapshots, returns an empty field.
+*/
+pub fn dominant_frequency_map(sim_history: &[Lattice], dt: f64) -> Vec<f64> {
+    let Some(first) = sim_history.first() else {
+        return vec![];
+    };
+    if sim_history.iter().any(|l| l.size() != first.size()) {
+        return vec![];
+    }
+    let series: Vec<Vec<f64>> = sim_history
+        .iter()
+        .map(|l| l.iter_cells().map(|(_, cell)| energy::total_energy(cell)).collect())
+        .collect();
+    let n_cells = series[0].len();
+    (0..n_cells)
+        .map(|i| {
+            let signal: Vec<f64> = series.iter().map(|snapshot| snapshot[i]).collect();
+            dominant_bin_frequency(&signal, dt)
+        })
+        .collect()
 }
 
 /*
 For global spatial harmonics.
-Wraps transport::compute_spatial_modes.
+Wraps transport::compute_spatial_modes, in its deterministic order.
 */
 pub fn detect_global_modes(lattice: &Lattice, var_i: usize, force_f: usize) -> Vec<SpatialMode> {
-    todo!();
+    transport::compute_spatial_modes(lattice, var_i, force_f)
 }
 
 /*
@@ -68,4 +250,129 @@ Conceptual; can be left for later.
 */
 pub fn mode_coupling_matrix(local_freq: &[f64], spatial_freq: &[f64]) -> Vec<Vec<f64>> {
     todo!();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::TAU;
+    use rand::{Rng, SeedableRng};
+    use crate::types::{LatticeCoord, N_FLATTENED};
+    use crate::utils::SimRng;
+
+    fn damped_sinusoid(n: usize) -> Vec<(f64, f64)> {
+        (0..n)
+            .map(|i| {
+                let t = i as f64 * 0.05;
+                (t, (-0.1 * t).exp() * (TAU * 2.0 * t).cos())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn envelope_recovers_decay_of_damped_sinusoid() {
+        for n in [512, 300] {
+            let history = damped_sinusoid(n);
+            let envelope = amplitude_envelope(&history);
+            assert_eq!(envelope.len(), n);
+            for i in 50..n - 50 {
+                let expected = (-0.1 * history[i].0).exp();
+                assert!((envelope[i] - expected).abs() < 0.05, "n = {n}, i = {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn spectral_entropy_separates_tone_from_noise() {
+        let tone: Vec<(f64, f64)> = (0..256).map(|i| (i as f64, (TAU * 8.0 * i as f64 / 256.0).sin())).collect();
+        let mut rng = SimRng::seed_from_u64(1);
+        let noise: Vec<(f64, f64)> = (0..256).map(|i| (i as f64, rng.random_range(-1.0..1.0))).collect();
+        assert!(spectral_entropy(&tone) < 0.05);
+        assert!(spectral_entropy(&noise) > 0.8);
+    }
+
+    #[test]
+    fn frequency_map_separates_halves_oscillating_at_different_rates() {
+        let dt = 0.1;
+        let history: Vec<Lattice> = (0..128)
+            .map(|s| {
+                let t = s as f64 * dt;
+                let mut lattice = Lattice::new((4, 2, 1)).unwrap();
+                for (coord, cell) in lattice.iter_cells_mut() {
+                    let f = if coord.x < 2 { 0.3125 } else { 1.25 };
+                    cell.e[1][2] = 2.0 + (TAU * f * t).sin();
+                }
+                lattice
+            })
+            .collect();
+        let map = dominant_frequency_map(&history, dt);
+        assert_eq!(map.len(), 8);
+        for (i, f) in map.iter().enumerate() {
+            let expected = if i % 4 < 2 { 0.3125 } else { 1.25 };
+            assert!((f - expected).abs() < 1e-9, "cell {i}: {f}");
+        }
+    }
+
+    fn sampled(f: f64, n: usize, dt: f64) -> Vec<(f64, f64)> {
+        (0..n).map(|i| (i as f64 * dt, 3.0 + (TAU * f * i as f64 * dt).sin())).collect()
+    }
+
+    #[test]
+    fn hann_window_beats_rectangular_off_bin() {
+        let f = 0.23;
+        let history = sampled(f, 20, 0.5);
+        let rectangular = (extract_frequency_from_timeseries(&history, utils::Window::Rectangular).unwrap() - f).abs();
+        let hann = (extract_frequency_from_timeseries(&history, utils::Window::Hann).unwrap() - f).abs();
+        assert!(hann < rectangular, "{hann} vs {rectangular}");
+    }
+
+    #[test]
+    fn zero_padded_interpolation_beats_the_bin_centroid() {
+        let f = 0.1137;
+        let history = sampled(f, 48, 1.0);
+        let centroid = (extract_frequency_from_timeseries(&history, utils::Window::Hann).unwrap() - f).abs();
+        let padded = (extract_frequency_interpolated(&history, utils::Window::Hann, 8).unwrap() - f).abs();
+        assert!(padded < centroid, "{padded} vs {centroid}");
+        assert!(padded < 1e-3);
+    }
+
+    #[test]
+    fn global_modes_follow_spatial_mode_order() {
+        let mut lattice = Lattice::new((4, 4, 1)).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[2][1] = 1.0 + (TAU * coord.x as f64 / 4.0).cos() + (TAU * coord.y as f64 / 4.0).cos();
+        }
+        let ks: Vec<_> = detect_global_modes(&lattice, 2, 1).iter().map(|m| m.k).collect();
+        assert_eq!(ks, vec![(-1, 0, 0), (0, -1, 0), (0, 1, 0), (1, 0, 0)]);
+    }
+
+    fn mode_along(entries: &[(usize, f64)]) -> OscillationMode {
+        let mut eigenvector = [0.0; N_FLATTENED];
+        for &(i, v) in entries {
+            eigenvector[i] = v;
+        }
+        OscillationMode { eigenvector, ..OscillationMode::default() }
+    }
+
+    #[test]
+    fn decomposition_reads_orthogonal_mode_coefficients() {
+        let modes = [mode_along(&[(0, 1.0), (1, 1.0)]), mode_along(&[(0, 1.0), (1, -1.0)]), mode_along(&[])];
+        let mut cell = CellState::default();
+        cell.e[0][0] = 3.0;
+        cell.e[0][1] = 3.0;
+        assert_eq!(mode_decomposition(&cell, &modes), vec![3.0, 0.0, 0.0]);
+        cell.e[0][1] = 1.0;
+        assert_eq!(mode_decomposition(&cell, &modes), vec![2.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn amplitude_field_follows_index_order() {
+        let modes = [mode_along(&[(0, 1.0), (1, 1.0)]), mode_along(&[(0, 1.0), (1, -1.0)])];
+        let mut lattice = Lattice::new((2, 1, 1)).unwrap();
+        lattice.at_mut(LatticeCoord { x: 1, y: 0, z: 0 }).unwrap().e[0][1] = 2.0;
+        assert_eq!(mode_amplitude_field(&lattice, &modes, 0), vec![0.0, 1.0]);
+        assert_eq!(mode_amplitude_field(&lattice, &modes, 1), vec![0.0, -1.0]);
+        assert!(mode_amplitude_field(&lattice, &modes, 2).is_empty());
+        assert!(mode_amplitude_field(&lattice, &[], 0).is_empty());
+    }
+}
@@ -8,11 +8,70 @@ Uses utils::matrix_ops for exponentials / eigen decomposition.
 Called by: evolution::step_redistribution, oscillation for mode info.
 */
 
-use crate::types::{CellState, N_FLATTENED, OscillationMode, RedistributionMatrix, TransferMask};
+use crate::types::{CellState, FORCES, N_FLATTENED, OscillationMode, RedistributionMatrix, TransferMask, VARS, join_index, split_index};
+use crate::utils;
+
+/*
+Taylor terms used by evolve_exact after scaling and squaring.
+*/
+pub const EXPONENTIAL_TERMS: usize = 16;
 
 /**/
 pub fn new_zero() -> RedistributionMatrix {
-    todo!();
+    RedistributionMatrix::default()
+}
+
+/**/
+impl RedistributionMatrix {
+    /*
+    Text form listing only the non-zero entries, one "from to rate" line each (a[from][to] = rate, as in set_oscillation),
+    in row-major order. Rates use Rust's shortest round-trip formatting, so from_sparse_string restores them exactly.
+    */
+    pub fn to_sparse_string(&self) -> String {
+        let mut out = String::new();
+        for (from, row) in self.a.iter().enumerate() {
+            for (to, &rate) in row.iter().enumerate() {
+                if rate != 0.0 {
+                    out.push_str(&format!("{from} {to} {rate:?}\n"));
+                }
+            }
+        }
+        out
+    }
+
+    /*
+    Parses to_sparse_string output. Blank lines and lines starting with '#' are skipped; unlisted entries are zero.
+    Errors (with the 1-based line number) on malformed lines, indices outside 0..N_FLATTENED,
+    non-finite rates, and entries given twice.
+    */
+    pub fn from_sparse_string(text: &str) -> Result<RedistributionMatrix, String> {
+        let mut matrix = RedistributionMatrix::default();
+        let mut seen = [[false; N_FLATTENED]; N_FLATTENED];
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [from, to, rate] = fields[..] else {
+                return Err(format!("line {}: expected \"from to rate\", got {line:?}", n + 1));
+            };
+            let index = |field: &str| match field.parse::<usize>() {
+                Ok(i) if i < N_FLATTENED => Ok(i),
+                _ => Err(format!("line {}: index {field:?} is not in 0..{N_FLATTENED}", n + 1)),
+            };
+            let (from, to) = (index(from)?, index(to)?);
+            let rate: f64 = match rate.parse() {
+                Ok(r) if f64::is_finite(r) => r,
+                _ => return Err(format!("line {}: rate {rate:?} is not a finite number", n + 1)),
+            };
+            if std::mem::replace(&mut seen[from][to], true) {
+                return Err(format!("line {}: entry ({from}, {to}) given twice", n + 1));
+            }
+            matrix.a[from][to] = rate;
+        }
+        Ok(matrix)
+    }
 }
 
 /*
@@ -23,11 +82,16 @@ Purpose:
 Create antisymmetric oscillatory coupling.
 */
 pub fn set_oscillation(matrix: &mut RedistributionMatrix, from: usize, to: usize, rate: f64) {
-    todo!();
+    matrix.a[from][to] = rate;
+    matrix.a[to][from] = -rate;
 }
 
 /*
 Checks mask before writing.
+Flat indices are decoded with types::split_index; both
+allow_var_to_var[var_from][var_to] and allow_force_to_force[force_from][force_to] must be set
+(so a same-variable transfer needs allow_var_to_var[v][v]).
+Then writes the pair like set_oscillation.
 */
 pub fn set_transfer(
     matrix: &mut RedistributionMatrix,
@@ -36,7 +100,83 @@ pub fn set_transfer(
     rate: f64,
     mask: &TransferMask,
 ) -> Result<(), &'static str> {
-    todo!();
+    if from >= N_FLATTENED || to >= N_FLATTENED {
+        return Err("transfer index out of range");
+    }
+    let (var_from, force_from) = split_index(from);
+    let (var_to, force_to) = split_index(to);
+    if !mask.allow_var_to_var[var_from][var_to] || !mask.allow_force_to_force[force_from][force_to] {
+        return Err("transfer not allowed by mask");
+    }
+    set_oscillation(matrix, from, to, rate);
+    Ok(())
+}
+
+/*
+set_oscillation between forces force_a and force_b of variable var, without the flat-index arithmetic.
+Errors on var >= VARS, a force >= FORCES, or force_a == force_b.
+*/
+pub fn set_force_oscillation(
+    matrix: &mut RedistributionMatrix,
+    var: usize,
+    force_a: usize,
+    force_b: usize,
+    rate: f64,
+) -> Result<(), &'static str> {
+    if var >= VARS {
+        return Err("variable index out of range");
+    }
+    if force_a >= FORCES || force_b >= FORCES {
+        return Err("force index out of range");
+    }
+    if force_a == force_b {
+        return Err("force oscillation needs two distinct forces");
+    }
+    set_oscillation(matrix, join_index(var, force_a), join_index(var, force_b), rate);
+    Ok(())
+}
+
+/*
+Rejects a matrix with non-finite entries or with |trace| > tol.
+A conservative (oscillatory) generator has zero trace, since trace(R) is the sum of its eigenvalues' growth rates.
+*/
+pub fn validate(matrix: &RedistributionMatrix, tol: f64) -> Result<(), &'static str> {
+    let m = utils::SquareMatrix::from(matrix.a);
+    if !m.0.iter().flatten().all(|x| x.is_finite()) {
+        return Err("redistribution matrix has non-finite entries");
+    }
+    if m.trace().abs() > tol {
+        return Err("redistribution matrix has non-zero trace");
+    }
+    Ok(())
+}
+
+/*
+max_j |Σ_i a[i][j]|: zero exactly when the total Σ E is conserved, since d(ΣE)/dt = 1ᵀ·R·E.
+Note that set_oscillation's antisymmetric pair conserves the norm |E|, not the sum,
+so it shows a defect of |rate|; a sum-conserving transfer at rate r from j to i
+writes a[i][j] += r and a[j][j] -= r.
+*/
+pub fn conservation_defect(matrix: &RedistributionMatrix) -> f64 {
+    (0..N_FLATTENED)
+        .map(|col| utils::column_sum(&matrix.a, col).abs())
+        .fold(0.0, f64::max)
+}
+
+/*
+Err naming the worst column when conservation_defect exceeds tol.
+*/
+pub fn assert_conservative(matrix: &RedistributionMatrix, tol: f64) -> Result<(), String> {
+    let (col, defect) = (0..N_FLATTENED)
+        .map(|col| (col, utils::column_sum(&matrix.a, col).abs()))
+        .fold((0, 0.0), |best, c| if c.1 > best.1 { c } else { best });
+    if defect > tol || defect.is_nan() {
+        let (var, force) = split_index(col);
+        return Err(format!(
+            "column {col} (var {var}, force {force}) sums to {defect:.3e}, so total energy is not conserved"
+        ));
+    }
+    Ok(())
 }
 
 /**/
@@ -75,12 +215,169 @@ Compute exp(R * dt) * E via utils::matrix_ops::exponential and multiply.
 Un-flatten back into cell.e.
 */
 pub fn evolve_exact(cell: &mut CellState, matrix: &RedistributionMatrix, dt: f64) {
-    todo!();
+    let propagator = utils::exponential(&matrix.a, dt, EXPONENTIAL_TERMS);
+    apply_propagator(cell, &propagator);
+}
+
+/*
+‖Pᵀ·P − I‖_F for P = exp(R * dt) as evolve_exact computes it.
+Zero for an exactly orthogonal propagator, which an antisymmetric R should give;
+growth means dt is too large for EXPONENTIAL_TERMS.
+*/
+pub fn exponential_norm_error(matrix: &RedistributionMatrix, dt: f64) -> f64 {
+    exponential_norm_error_with_terms(matrix, dt, EXPONENTIAL_TERMS)
+}
+
+/*
+exponential_norm_error with an explicit number of Taylor terms, for choosing one.
+*/
+pub fn exponential_norm_error_with_terms(matrix: &RedistributionMatrix, dt: f64, terms: usize) -> f64 {
+    let p = utils::SquareMatrix::from(utils::exponential(&matrix.a, dt, terms));
+    let mut defect = utils::multiply(&p.transpose().0, &p.0);
+    for (i, row) in defect.iter_mut().enumerate() {
+        row[i] -= 1.0;
+    }
+    utils::SquareMatrix(defect).frobenius_norm()
+}
+
+/*
+E ← P·E for a precomputed propagator P = exp(R * dt).
+Lets a caller evolving many cells compute the exponential once.
+*/
+pub fn apply_propagator(cell: &mut CellState, propagator: &[[f64; N_FLATTENED]; N_FLATTENED]) {
+    *cell = CellState::from_flat(&utils::apply(propagator, &cell.flatten()));
 }
 
 /*
 Optional fallback with ODE solver for stiff cases.
+Integrates dE/dt = R·E over dt with RK4 and step doubling: each substep is compared against two half steps,
+and accepted once the max-norm difference / 15 is within tol.
+Errors if tol is not positive or the substep underflows.
 */
-pub fn evolve_adaptive(/*…*/) -> Result<(), &'static str> {
-    todo!();
+pub fn evolve_adaptive(cell: &mut CellState, matrix: &RedistributionMatrix, dt: f64, tol: f64) -> Result<(), &'static str> {
+    if tol.is_nan() || tol <= 0.0 {
+        return Err("adaptive tolerance must be positive");
+    }
+    let mut e = cell.flatten();
+    let mut t = 0.0;
+    let mut h = dt;
+    while t < dt {
+        h = h.min(dt - t);
+        if h <= dt * f64::EPSILON {
+            return Err("adaptive step size underflow");
+        }
+        let full = rk4_step(&matrix.a, &e, h);
+        let half = rk4_step(&matrix.a, &rk4_step(&matrix.a, &e, 0.5 * h), 0.5 * h);
+        let err = full.iter().zip(&half).map(|(a, b)| (a - b).abs()).fold(0.0, f64::max) / 15.0;
+        if err <= tol {
+            e = half;
+            t += h;
+            if err < tol / 32.0 {
+                h *= 2.0;
+            }
+        } else {
+            h *= 0.5;
+        }
+    }
+    *cell = CellState::from_flat(&e);
+    Ok(())
+}
+
+fn rk4_step(a: &[[f64; N_FLATTENED]; N_FLATTENED], e: &[f64; N_FLATTENED], h: f64) -> [f64; N_FLATTENED] {
+    let offset = |base: &[f64; N_FLATTENED], k: &[f64; N_FLATTENED], f: f64| {
+        let mut out = *base;
+        for (o, k) in out.iter_mut().zip(k) {
+            *o += f * k;
+        }
+        out
+    };
+    let k1 = utils::apply(a, e);
+    let k2 = utils::apply(a, &offset(e, &k1, 0.5 * h));
+    let k3 = utils::apply(a, &offset(e, &k2, 0.5 * h));
+    let k4 = utils::apply(a, &offset(e, &k3, h));
+    let mut out = *e;
+    for i in 0..N_FLATTENED {
+        out[i] += h / 6.0 * (k1[i] + 2.0 * k2[i] + 2.0 * k3[i] + k4[i]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_transfer_decodes_flat_indices_for_the_mask() {
+        let mut matrix = RedistributionMatrix::default();
+        let mut mask = TransferMask::default();
+        assert!(set_transfer(&mut matrix, join_index(0, 1), join_index(1, 1), 0.2, &mask).is_err());
+        mask.allow_var_to_var[0][1] = true;
+        assert!(set_transfer(&mut matrix, join_index(0, 1), join_index(1, 1), 0.2, &mask).is_err());
+        mask.allow_force_to_force[1][1] = true;
+        assert!(set_transfer(&mut matrix, join_index(0, 1), join_index(1, 1), 0.2, &mask).is_ok());
+        assert_eq!(matrix.a[1][5], 0.2);
+        assert_eq!(matrix.a[5][1], -0.2);
+    }
+
+    #[test]
+    fn more_series_terms_reduce_the_norm_error() {
+        let mut matrix = RedistributionMatrix::default();
+        set_oscillation(&mut matrix, 0, 5, 3.0);
+        set_oscillation(&mut matrix, 5, 11, 1.0);
+        let errors: Vec<f64> = [1, 2, 3, 4, 6].iter().map(|&terms| exponential_norm_error_with_terms(&matrix, 1.0, terms)).collect();
+        assert!(errors.windows(2).all(|w| w[1] < w[0]), "{errors:?}");
+        assert!(exponential_norm_error(&matrix, 1.0) < 1e-10);
+    }
+
+    #[test]
+    fn defect_is_zero_for_sum_conserving_transfers_only() {
+        let mut transfer = RedistributionMatrix::default();
+        transfer.a[3][7] += 0.4;
+        transfer.a[7][7] -= 0.4;
+        assert_eq!(conservation_defect(&transfer), 0.0);
+        assert!(assert_conservative(&transfer, 1e-12).is_ok());
+
+        // The antisymmetric pair conserves the norm, not the sum.
+        let mut oscillation = RedistributionMatrix::default();
+        set_oscillation(&mut oscillation, 3, 7, 0.4);
+        assert!((conservation_defect(&oscillation) - 0.4).abs() < 1e-15);
+
+        let mut raw = RedistributionMatrix::default();
+        raw.a[2][5] = 1.0;
+        assert_eq!(conservation_defect(&raw), 1.0);
+        assert!(assert_conservative(&raw, 1e-12).is_err());
+    }
+
+    #[test]
+    fn sparse_string_round_trips_exactly() {
+        let mut matrix = new_zero();
+        set_oscillation(&mut matrix, 0, 7, 0.1);
+        set_oscillation(&mut matrix, 19, 3, 1.0 / 3.0);
+        let text = matrix.to_sparse_string();
+        assert_eq!(text.lines().count(), 4);
+        assert_eq!(RedistributionMatrix::from_sparse_string(&text).unwrap().a, matrix.a);
+        assert!(RedistributionMatrix::from_sparse_string("# comment\n\n 1 2 0.5 \n").is_ok());
+        for bad in ["20 1 0.5", "1 2", "1 2 nan", "1 2 0.1\n1 2 0.2", "-1 2 3"] {
+            assert!(RedistributionMatrix::from_sparse_string(bad).is_err(), "{bad:?}");
+        }
+        assert!(RedistributionMatrix::from_sparse_string("1 2 0.1\n1 2 0.2").err().unwrap().contains("line 2"));
+    }
+
+    #[test]
+    fn force_oscillation_swaps_one_variables_channels_only() {
+        let mut matrix = new_zero();
+        set_force_oscillation(&mut matrix, 2, 1, 3, 1.0).unwrap();
+        assert!(set_force_oscillation(&mut matrix, VARS, 1, 3, 1.0).is_err());
+        assert!(set_force_oscillation(&mut matrix, 1, FORCES, 3, 1.0).is_err());
+        assert!(set_force_oscillation(&mut matrix, 1, 3, 3, 1.0).is_err());
+        let mut cell = CellState::default();
+        cell.e[2][1] = 1.0;
+        cell.e[0][0] = 0.7;
+        cell.e[2][0] = 0.3;
+        // A quarter period moves all of e[2][1] into e[2][3].
+        evolve_exact(&mut cell, &matrix, std::f64::consts::FRAC_PI_2);
+        assert!(cell.e[2][1].abs() < 1e-9);
+        assert!((cell.e[2][3].abs() - 1.0).abs() < 1e-9);
+        assert!((cell.e[0][0] - 0.7).abs() < 1e-12 && (cell.e[2][0] - 0.3).abs() < 1e-12);
+    }
 }
@@ -0,0 +1,104 @@
+#![forbid(unsafe_code)]
+
+/*
+Purpose: External forcing hooked into Simulation::step.
+
+Uses Lattice, LatticeCoord from types and lattice.
+Called by: evolution::Simulation::step, once per step after transport.
+*/
+
+use crate::lattice::Lattice;
+use crate::types::{FORCES, LatticeCoord, VARS};
+
+/*
+A source term adds (or removes) energy each step; time is the simulation time at the start of the step.
+Sources are not conservative, so Simulation::verify_energy_conservation reports what they inject.
+*/
+pub trait SourceTerm: Send + Sync {
+    fn apply(&self, lattice: &mut Lattice, time: f64, dt: f64);
+}
+
+/*
+Adds rate * dt to e[var][force] of the cell at coord every step.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConstantSource {
+    pub coord: LatticeCoord,
+    pub var: usize,
+    pub force: usize,
+    pub rate: f64,
+}
+
+/**/
+impl SourceTerm for ConstantSource {
+    fn apply(&self, lattice: &mut Lattice, _time: f64, dt: f64) {
+        inject(lattice, self.coord, self.var, self.force, self.rate * dt);
+    }
+}
+
+/*
+Adds amplitude * sin(angular_frequency * time + phase) * dt to e[var][force] of the cell at coord,
+sampling the driver at the start of each step. Negative half-cycles drain the channel.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SinusoidalDriver {
+    pub coord: LatticeCoord,
+    pub var: usize,
+    pub force: usize,
+    pub amplitude: f64,
+    pub angular_frequency: f64,
+    pub phase: f64,
+}
+
+/**/
+impl SourceTerm for SinusoidalDriver {
+    fn apply(&self, lattice: &mut Lattice, time: f64, dt: f64) {
+        let rate = self.amplitude * (self.angular_frequency * time + self.phase).sin();
+        inject(lattice, self.coord, self.var, self.force, rate * dt);
+    }
+}
+
+/*
+Out-of-bounds coordinates and channels are ignored.
+*/
+fn inject(lattice: &mut Lattice, coord: LatticeCoord, var: usize, force: usize, amount: f64) {
+    if var >= VARS || force >= FORCES {
+        return;
+    }
+    if let Some(cell) = lattice.at_mut(coord) {
+        cell.e[var][force] += amount;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constant_and_sinusoidal_sources_inject_their_rates() {
+        let mut lattice = Lattice::new((2, 3, 4)).unwrap();
+        let coord = LatticeCoord { x: 1, y: 1, z: 1 };
+        ConstantSource { coord, var: 1, force: 2, rate: 2.0 }.apply(&mut lattice, 0.0, 0.5);
+        assert_eq!(lattice.at(coord).unwrap().e[1][2], 1.0);
+        let driver = SinusoidalDriver {
+            coord,
+            var: 1,
+            force: 2,
+            amplitude: 1.0,
+            angular_frequency: std::f64::consts::PI,
+            phase: 0.0,
+        };
+        driver.apply(&mut lattice, 0.5, 0.5);
+        assert!((lattice.at(coord).unwrap().e[1][2] - 1.5).abs() < 1e-12);
+        driver.apply(&mut lattice, 1.5, 0.5);
+        assert!((lattice.at(coord).unwrap().e[1][2] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn out_of_range_sources_are_ignored() {
+        let mut lattice = Lattice::new((2, 2, 2)).unwrap();
+        ConstantSource { coord: LatticeCoord { x: 2, y: 0, z: 0 }, var: 0, force: 0, rate: 1.0 }.apply(&mut lattice, 0.0, 1.0);
+        ConstantSource { coord: LatticeCoord::default(), var: VARS, force: 0, rate: 1.0 }.apply(&mut lattice, 0.0, 1.0);
+        assert!(lattice.cells().iter().all(|cell| cell.e.iter().flatten().all(|&v| v == 0.0)));
+    }
+}
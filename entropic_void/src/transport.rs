@@ -9,7 +9,28 @@ Called by: evolution::step_transport, oscillation for global modes, visualizatio
 */
 
 use crate::lattice::Lattice;
-use crate::types::{CellState, FORCES, SpatialMode, VARS};
+use crate::types::{CellState, Direction, FORCES, LatticeCoord, SpatialMode, VARS};
+use crate::utils;
+
+/*
+Per-direction coupling factors, indexed like Direction::ALL.
+All ones is plain isotropic transport.
+*/
+pub const ISOTROPIC: [f64; 6] = [1.0; 6];
+
+/*
+How a bond exchanges energy in distribute_to_neighbors_with.
+*/
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransportScheme {
+    // exchange_exact per bond: exact for the pair, and invertible.
+    #[default]
+    ExactExchange,
+    // First-order E_a += c·dt·(E_b - E_a), E_b -= the same, per bond.
+    // Cheaper, and matches ExactExchange only to first order in dt. The bond total is preserved,
+    // but the update is not invertible and overshoots (values of the pair swap order, may go negative) once c·dt > 1/2.
+    LinearDiffusion,
+}
 
 /*
 Effect:
@@ -24,7 +45,22 @@ pub fn exchange_exact(
     coupling: f64,
     dt: f64,
 ) {
-    todo!();
+    exchange_values(&mut cell_a.e[var_i][force_f], &mut cell_b.e[var_i][force_f], coupling, dt);
+}
+
+fn exchange_values(a: &mut f64, b: &mut f64, coupling: f64, dt: f64) {
+    // dE_a/dt = c (E_b - E_a), dE_b/dt = c (E_a - E_b):
+    // the sum is fixed, the half-difference decays as exp(-2 c dt).
+    let mean = 0.5 * (*a + *b);
+    let half_diff = 0.5 * (*a - *b) * (-2.0 * coupling * dt).exp();
+    *a = mean + half_diff;
+    *b = mean - half_diff;
+}
+
+fn exchange_linear(a: &mut f64, b: &mut f64, coupling: f64, dt: f64) {
+    let flow = coupling * dt * (*b - *a);
+    *a += flow;
+    *b -= flow;
 }
 
 /*
@@ -38,18 +74,350 @@ pub fn distribute_to_neighbors(
     coupling_matrix: &[[f64; FORCES]; VARS],
     dt: f64,
 ) {
-    todo!();
+    distribute_to_neighbors_anisotropic(lattice, coupling_matrix, &ISOTROPIC, dt);
 }
 
-/**/
+/*
+As distribute_to_neighbors, with coupling_matrix[var_i][force_f] scaled per direction.
+Each cell exchanges once with its +x, +y, +z neighbor (periodic), so every bond is visited once.
+The bond between a cell and its +d neighbor uses the mean of the +d and -d factors,
+keeping each exchange symmetric and therefore conservative.
+*/
+pub fn distribute_to_neighbors_anisotropic(
+    lattice: &mut Lattice,
+    coupling_matrix: &[[f64; FORCES]; VARS],
+    direction_scale: &[f64; 6],
+    dt: f64,
+) {
+    distribute_to_neighbors_with(lattice, coupling_matrix, direction_scale, TransportScheme::ExactExchange, dt);
+}
+
+/*
+As distribute_to_neighbors_anisotropic, exchanging each bond by the given scheme.
+*/
+pub fn distribute_to_neighbors_with(
+    lattice: &mut Lattice,
+    coupling_matrix: &[[f64; FORCES]; VARS],
+    direction_scale: &[f64; 6],
+    scheme: TransportScheme,
+    dt: f64,
+) {
+    let exchange = match scheme {
+        TransportScheme::ExactExchange => exchange_values,
+        TransportScheme::LinearDiffusion => exchange_linear,
+    };
+    for (here, d) in bonds(lattice) {
+        exchange_bond(lattice, here, d, coupling_matrix, direction_scale, exchange, dt);
+    }
+}
+
+/*
+Exact inverse of distribute_to_neighbors_anisotropic with the same arguments:
+undoes each bond exchange (exchange_exact with -dt) in reverse order.
+Backward diffusion amplifies differences, so only undo steps that were actually taken.
+*/
+pub fn invert_distribute_to_neighbors(
+    lattice: &mut Lattice,
+    coupling_matrix: &[[f64; FORCES]; VARS],
+    direction_scale: &[f64; 6],
+    dt: f64,
+) {
+    for (here, d) in bonds(lattice).into_iter().rev() {
+        exchange_bond(lattice, here, d, coupling_matrix, direction_scale, exchange_values, -dt);
+    }
+}
+
+/*
+Post-processing smoother: iterations passes of distribute_to_neighbors on every channel with coupling·dt = strength,
+so each bond pass shrinks the pair's difference by exp(-2 strength).
+Every exchange is pairwise, so each channel's global total is kept up to float rounding.
+A non-positive or non-finite strength leaves the lattice unchanged.
+*/
+pub fn smooth(lattice: &mut Lattice, iterations: usize, strength: f64) {
+    if !strength.is_finite() || strength <= 0.0 {
+        return;
+    }
+    let coupling = [[strength; FORCES]; VARS];
+    for _ in 0..iterations {
+        distribute_to_neighbors(lattice, &coupling, 1.0);
+    }
+}
+
+/*
+Cross-species transport: across every bond, channel e[i][f] of a cell exchanges with e[j][f] of its +d neighbor
+at rate cross_coupling[i][j] (scaled like distribute_to_neighbors_anisotropic), for every i != j and force f.
+The diagonal is ignored; same-variable transport is the per-channel coupling matrix.
+Each exchange is a pairwise exchange_exact, so total energy is conserved for any matrix, while per-variable totals
+are not (that is the point). A symmetric matrix makes the operator independent of bond orientation;
+an asymmetric one also drifts energy along +d.
+*/
+pub fn distribute_cross_variable(
+    lattice: &mut Lattice,
+    cross_coupling: &[[f64; VARS]; VARS],
+    direction_scale: &[f64; 6],
+    dt: f64,
+) {
+    for (here, d) in bonds(lattice) {
+        cross_bond(lattice, here, d, cross_coupling, direction_scale, dt);
+    }
+}
+
+/*
+Exact inverse of distribute_cross_variable with the same arguments.
+*/
+pub fn invert_distribute_cross_variable(
+    lattice: &mut Lattice,
+    cross_coupling: &[[f64; VARS]; VARS],
+    direction_scale: &[f64; 6],
+    dt: f64,
+) {
+    for (here, d) in bonds(lattice).into_iter().rev() {
+        cross_bond(lattice, here, d, cross_coupling, direction_scale, -dt);
+    }
+}
+
+/*
+Energy one exchange_exact pass over every channel would move from cell_a to cell_b (negative when b gives to a),
+without changing either cell.
+*/
+pub fn bond_transfer(cell_a: &CellState, cell_b: &CellState, coupling_matrix: &[[f64; FORCES]; VARS], dt: f64) -> f64 {
+    let mut moved = 0.0;
+    for (var_i, row) in coupling_matrix.iter().enumerate() {
+        for (force_f, &coupling) in row.iter().enumerate() {
+            let (mut a, mut b) = (cell_a.e[var_i][force_f], cell_b.e[var_i][force_f]);
+            exchange_values(&mut a, &mut b, coupling, dt);
+            moved += cell_a.e[var_i][force_f] - a;
+        }
+    }
+    moved
+}
+
+/*
+Every (cell, forward direction) bond in the order distribute_to_neighbors visits them.
+*/
+fn bonds(lattice: &Lattice) -> Vec<(LatticeCoord, Direction)> {
+    let forward = [Direction::PosX, Direction::PosY, Direction::PosZ];
+    lattice
+        .iter_cells()
+        .flat_map(|(here, _)| forward.into_iter().map(move |d| (here, d)))
+        .collect()
+}
+
+fn exchange_bond(
+    lattice: &mut Lattice,
+    here: LatticeCoord,
+    d: Direction,
+    coupling_matrix: &[[f64; FORCES]; VARS],
+    direction_scale: &[f64; 6],
+    exchange: fn(&mut f64, &mut f64, f64, f64),
+    dt: f64,
+) {
+    let scale = 0.5 * (direction_scale[d as usize] + direction_scale[d.opposite() as usize]);
+    let there = lattice.shifted(&here, d.offset());
+    if let Some((cell_a, cell_b)) = lattice.pair_mut(here, there) {
+        for (var_i, row) in coupling_matrix.iter().enumerate() {
+            for (force_f, &coupling) in row.iter().enumerate() {
+                exchange(&mut cell_a.e[var_i][force_f], &mut cell_b.e[var_i][force_f], coupling * scale, dt);
+            }
+        }
+    }
+}
+
+fn cross_bond(
+    lattice: &mut Lattice,
+    here: LatticeCoord,
+    d: Direction,
+    cross_coupling: &[[f64; VARS]; VARS],
+    direction_scale: &[f64; 6],
+    dt: f64,
+) {
+    let scale = 0.5 * (direction_scale[d as usize] + direction_scale[d.opposite() as usize]);
+    let there = lattice.shifted(&here, d.offset());
+    if let Some((cell_a, cell_b)) = lattice.pair_mut(here, there) {
+        for (var_i, row) in cross_coupling.iter().enumerate() {
+            for (var_j, &coupling) in row.iter().enumerate() {
+                if var_i == var_j || coupling == 0.0 {
+                    continue;
+                }
+                for force_f in 0..FORCES {
+                    exchange_values(&mut cell_a.e[var_i][force_f], &mut cell_b.e[var_j][force_f], coupling * scale, dt);
+                }
+            }
+        }
+    }
+}
+
+/*
+Largest dt for which explicit diffusion with these couplings is stable: 1 / (2 Σ_axis c_max · s_axis),
+where s_axis is the mean of the axis' two direction factors.
+exchange_exact itself never blows up, but past this the per-bond splitting overshoots and loses accuracy.
+Infinite when there is no coupling.
+*/
+pub fn max_stable_dt(coupling_matrix: &[[f64; FORCES]; VARS], direction_scale: &[f64; 6]) -> f64 {
+    let c_max = coupling_matrix.iter().flatten().fold(0.0, |m: f64, c| m.max(c.abs()));
+    let axis_sum: f64 = [Direction::PosX, Direction::PosY, Direction::PosZ]
+        .iter()
+        .map(|&d| 0.5 * (direction_scale[d as usize] + direction_scale[d.opposite() as usize]).abs())
+        .sum();
+    let rate = 2.0 * c_max * axis_sum;
+    if rate > 0.0 { 1.0 / rate } else { f64::INFINITY }
+}
+
+/*
+|k| in radians per cell for integer mode k on a lattice of this size: 2π·|(k_x/N_x, k_y/N_y, k_z/N_z)|,
+each axis normalized by its own length. 0.0 if any dimension is 0.
+*/
 pub fn fourier_mode_frequency(k: (isize, isize, isize), size: (usize, usize, usize)) -> f64 {
-    todo!();
+    if size.0 == 0 || size.1 == 0 || size.2 == 0 {
+        return 0.0;
+    }
+    let axis = |k: isize, n: usize| 2.0 * std::f64::consts::PI * k as f64 / n as f64;
+    axis(k.0, size.0).hypot(axis(k.1, size.1)).hypot(axis(k.2, size.2))
 }
 
 /*
 Uses utils::fft::fft_3d to compute FFT of E[c][var_i][force_f].
 Converts to SpatialMode list.
+One mode per non-DC bin, k signed (bins above N/2 fold to negative), amplitude |X_k| / N_cells,
+frequency from fourier_mode_frequency. Bins below 1e-12 of the strongest are dropped.
+Sorted by amplitude, largest first; amplitudes equal to that relative precision are ties,
+broken by smaller |k| first and then lexicographically smaller k, so symmetric fields give a fixed order.
+Empty if the channel is out of range.
 */
 pub fn compute_spatial_modes(lattice: &Lattice, var_i: usize, force_f: usize) -> Vec<SpatialMode> {
-    todo!();
+    let spectrum = utils::fft_3d(lattice, var_i, force_f);
+    if spectrum.is_empty() {
+        return vec![];
+    }
+    let size = lattice.size();
+    let n = spectrum.len() as f64;
+    let signed = |i: usize, len: usize| if i <= len / 2 { i as isize } else { i as isize - len as isize };
+    let mut modes: Vec<SpatialMode> = lattice
+        .iter_cells()
+        .zip(spectrum.iter())
+        .filter(|((c, _), _)| (c.x, c.y, c.z) != (0, 0, 0))
+        .map(|((c, _), x)| {
+            let k = (signed(c.x, size.0), signed(c.y, size.1), signed(c.z, size.2));
+            SpatialMode { k, amplitude: x.norm() / n, frequency: fourier_mode_frequency(k, size) }
+        })
+        .collect();
+    let strongest = modes.iter().map(|m| m.amplitude).fold(0.0, f64::max);
+    if strongest <= 0.0 {
+        return vec![];
+    }
+    let level = |m: &SpatialMode| (m.amplitude / strongest * 1e12).round() as i64;
+    modes.retain(|m| level(m) > 0);
+    let k_sq = |m: &SpatialMode| m.k.0 * m.k.0 + m.k.1 * m.k.1 + m.k.2 * m.k.2;
+    modes.sort_by(|a, b| {
+        level(b)
+            .cmp(&level(a))
+            .then(k_sq(a).cmp(&k_sq(b)))
+            .then(a.k.cmp(&b.k))
+    });
+    modes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conservation::{energy_budget, DensityAccumulator};
+
+    #[test]
+    fn perturbation_spreads_faster_along_strong_axis() {
+        let mut lattice = Lattice::new((9, 9, 9)).unwrap();
+        lattice.at_mut(LatticeCoord { x: 4, y: 4, z: 4 }).unwrap().e[0][0] = 100.0;
+        let mut coupling = [[0.0; FORCES]; VARS];
+        coupling[0][0] = 0.2;
+        let scale = [3.0, 3.0, 1.0, 1.0, 0.2, 0.2];
+        for _ in 0..10 {
+            distribute_to_neighbors_anisotropic(&mut lattice, &coupling, &scale, 0.1);
+        }
+        let along_x = lattice.at(LatticeCoord { x: 6, y: 4, z: 4 }).unwrap().e[0][0];
+        let along_z = lattice.at(LatticeCoord { x: 4, y: 4, z: 6 }).unwrap().e[0][0];
+        assert!(along_x > along_z);
+        let total: f64 = lattice.cells().iter().map(|cell| cell.e[0][0]).sum();
+        assert!((total - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn tied_modes_come_out_in_the_defined_order() {
+        let mut lattice = Lattice::new((8, 8, 1)).unwrap();
+        let step = std::f64::consts::TAU / 8.0;
+        for (coord, cell) in lattice.iter_cells_mut() {
+            let (x, y) = (step * coord.x as f64, step * coord.y as f64);
+            cell.e[0][0] = 1.0 + x.cos() + y.cos() + 0.5 * (2.0 * x).cos();
+        }
+        let modes = compute_spatial_modes(&lattice, 0, 0);
+        let ks: Vec<_> = modes.iter().map(|m| m.k).collect();
+        assert_eq!(ks, vec![(-1, 0, 0), (0, -1, 0), (0, 1, 0), (1, 0, 0), (-2, 0, 0), (2, 0, 0)]);
+        assert!((modes[0].amplitude - 0.5).abs() < 1e-12);
+        assert!((modes[4].amplitude - 0.25).abs() < 1e-12);
+        assert_eq!(modes[5].frequency, fourier_mode_frequency((2, 0, 0), (8, 8, 1)));
+    }
+
+    #[test]
+    fn schemes_agree_to_first_order_at_small_dt() {
+        let mut start = Lattice::new((5, 4, 3)).unwrap();
+        for (c, cell) in start.iter_cells_mut() {
+            cell.e[0][0] = 1.0 + ((c.x * 7 + c.y * 3 + c.z * 11) % 13) as f64;
+            cell.e[2][3] = 0.5 * c.y as f64;
+        }
+        let coupling = [[0.5; FORCES]; VARS];
+        let run = |scheme, dt| {
+            let mut lattice = start.clone();
+            distribute_to_neighbors_with(&mut lattice, &coupling, &ISOTROPIC, scheme, dt);
+            lattice
+        };
+        let largest = |a: &Lattice, b: &Lattice| {
+            a.cells()
+                .iter()
+                .zip(b.cells())
+                .flat_map(|(a, b)| a.e.iter().flatten().zip(b.e.iter().flatten()))
+                .fold(0.0, |m: f64, (a, b)| m.max((a - b).abs()))
+        };
+        for dt in [1e-3, 1e-4] {
+            let exact = run(TransportScheme::ExactExchange, dt);
+            let linear = run(TransportScheme::LinearDiffusion, dt);
+            let change = largest(&exact, &start);
+            assert!(change > 0.0);
+            assert!(largest(&exact, &linear) < 10.0 * dt * change, "dt {dt}");
+            let total = |l: &Lattice| l.cells().iter().map(|c| c.e[0][0]).sum::<f64>();
+            assert!((total(&linear) - total(&start)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn bond_transfer_matches_the_exchange_without_moving_energy() {
+        let mut a = CellState::default();
+        let mut b = CellState::default();
+        a.e[1][2] = 4.0;
+        b.e[1][2] = 1.0;
+        let coupling = [[0.3; FORCES]; VARS];
+        let moved = bond_transfer(&a, &b, &coupling, 0.2);
+        assert_eq!(a.e[1][2], 4.0);
+        let (mut x, mut y) = (4.0, 1.0);
+        exchange_values(&mut x, &mut y, 0.3, 0.2);
+        assert_eq!(moved, 4.0 - x);
+        assert_eq!(bond_transfer(&b, &a, &coupling, 0.2), -moved);
+    }
+
+    #[test]
+    fn smoothing_reduces_variance_and_keeps_channel_totals() {
+        let mut lattice = Lattice::new((6, 6, 6)).unwrap();
+        for (c, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = ((c.x * 13 + c.y * 7 + c.z * 3) % 5) as f64;
+            cell.e[4][3] = 0.1 * ((c.x + c.y * 2) % 3) as f64;
+        }
+        let before = energy_budget(&lattice);
+        let variance = DensityAccumulator::from_lattice(&lattice).variance();
+        let untouched = lattice.clone();
+        smooth(&mut lattice, 3, -1.0);
+        assert_eq!(lattice.cells(), untouched.cells());
+        smooth(&mut lattice, 3, 0.2);
+        assert!(DensityAccumulator::from_lattice(&lattice).variance() < 0.5 * variance);
+        let after = energy_budget(&lattice);
+        for (a, b) in before.per_channel.iter().flatten().zip(after.per_channel.iter().flatten()) {
+            assert!((a - b).abs() <= 1e-12 * a.abs().max(1.0));
+        }
+    }
 }
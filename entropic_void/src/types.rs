@@ -17,22 +17,63 @@ pub const FORCES: usize = 4;
 /**/
 pub const N_FLATTENED: usize = VARS * FORCES;
 
+/*
+Flat channel index → (var, force), matching CellState::flatten.
+*/
+pub fn split_index(flat: usize) -> (usize, usize) {
+    (flat / FORCES, flat % FORCES)
+}
+
+/*
+(var, force) → flat channel index, matching CellState::flatten.
+*/
+pub fn join_index(var: usize, force: usize) -> usize {
+    var * FORCES + force
+}
+
 /*
 Energy per variable per force in one cell.
 */
-#[derive(Default)]
+#[derive(Default, Clone, Debug, PartialEq)]
 pub struct CellState {
     pub e: [[f64; FORCES]; VARS],
 }
 
+/*
+Flat layout is row-major: e[var][force] ↔ flat[var * FORCES + force].
+Every N_FLATTENED-sized vector or matrix (RedistributionMatrix, OscillationMode::eigenvector) uses it.
+*/
+impl CellState {
+    /**/
+    pub fn flatten(&self) -> [f64; N_FLATTENED] {
+        let mut flat = [0.0; N_FLATTENED];
+        for (i, value) in flat.iter_mut().enumerate() {
+            let (var, force) = split_index(i);
+            *value = self.e[var][force];
+        }
+        flat
+    }
+
+    /**/
+    pub fn from_flat(flat: &[f64; N_FLATTENED]) -> CellState {
+        let mut cell = CellState::default();
+        for (i, value) in flat.iter().enumerate() {
+            let (var, force) = split_index(i);
+            cell.e[var][force] = *value;
+        }
+        cell
+    }
+}
+
 /**/
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct RedistributionMatrix {
     pub a: [[f64; N_FLATTENED]; N_FLATTENED],
 }
 
 /**/
-#[derive(Default)]
+#[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "config", derive(serde::Serialize, serde::Deserialize))]
 pub enum VariableConstraint {
     #[default]
     Free,
@@ -41,21 +82,24 @@ pub enum VariableConstraint {
 }
 
 /**/
-#[derive(Default)]
+#[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "config", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExpressionConstraint {
     pub locked: bool,
     pub force_pct: [f64; FORCES],
 }
 
 /**/
-#[derive(Default)]
+#[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "config", derive(serde::Serialize, serde::Deserialize))]
 pub struct TransferMask {
     pub allow_var_to_var: [[bool; VARS]; VARS],
     pub allow_force_to_force: [[bool; FORCES]; FORCES],
 }
 
 /**/
-#[derive(Default)]
+#[derive(Default, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "config", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConstraintSet {
     pub var_constraints: [VariableConstraint; VARS],
     pub expr_constraints: [ExpressionConstraint; VARS],
@@ -80,7 +124,7 @@ pub struct SpatialMode {
 }
 
 /**/
-#[derive(Default)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct LatticeCoord {
     pub x: usize,
     pub y: usize,
@@ -88,7 +132,7 @@ pub struct LatticeCoord {
 }
 
 /**/
-#[derive(Default)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Direction {
     #[default]
     PosX,
@@ -99,6 +143,40 @@ pub enum Direction {
     NegZ,
 }
 
+/**/
+impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Self::PosX, Self::NegX,
+        Self::PosY, Self::NegY,
+        Self::PosZ, Self::NegZ,
+    ];
+
+    pub fn offset(self) -> (isize, isize, isize) {
+        match self {
+            Self::PosX => ( 1, 0, 0),
+            Self::NegX => (-1, 0, 0),
+            Self::PosY => ( 0, 1, 0),
+            Self::NegY => ( 0,-1, 0),
+            Self::PosZ => ( 0, 0, 1),
+            Self::NegZ => ( 0, 0,-1),
+        }
+    }
+
+    /*
+    The direction pointing the other way along the same axis.
+    */
+    pub fn opposite(self) -> Direction {
+        match self {
+            Self::PosX => Self::NegX,
+            Self::NegX => Self::PosX,
+            Self::PosY => Self::NegY,
+            Self::NegY => Self::PosY,
+            Self::PosZ => Self::NegZ,
+            Self::NegZ => Self::PosZ,
+        }
+    }
+}
+
 /*
 Extra?
 */
@@ -123,3 +201,34 @@ pub enum ForceKind {
     Weak,
     Strong,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flatten_round_trips() {
+        let mut cell = CellState::default();
+        for (var, row) in cell.e.iter_mut().enumerate() {
+            for (force, value) in row.iter_mut().enumerate() {
+                *value = (var * 10 + force) as f64;
+            }
+        }
+        let flat = cell.flatten();
+        assert_eq!(flat[join_index(2, 3)], 23.0);
+        assert_eq!(CellState::from_flat(&flat), cell);
+    }
+
+    #[test]
+    fn split_index_inverts_join_index() {
+        for var in 0..VARS {
+            for force in 0..FORCES {
+                assert_eq!(split_index(join_index(var, force)), (var, force));
+            }
+        }
+        for flat in 0..N_FLATTENED {
+            let (var, force) = split_index(flat);
+            assert_eq!(join_index(var, force), flat);
+        }
+    }
+}
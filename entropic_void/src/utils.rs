@@ -6,20 +6,104 @@ Used by: redistribution, oscillation, visualization, conservation.
 */
 
 use num_complex::Complex64;
-use rand::rngs::SmallRng;
+use rand::Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use crate::lattice::Lattice;
+use crate::types::{FORCES, VARS};
+
+/*
+PRNG behind every seeded constructor (generate_lattice, init, simulate_tick).
+By default SmallRng: fast, but its algorithm may change between rand releases and platforms,
+so a seed only reproduces a run on the same build.
+With the reproducible feature, ChaCha8Rng: rand_chacha fixes its raw output stream (next_u32 / next_u64)
+for a given seed across platforms and versions. Values are drawn from it through rand's distributions, which
+may change between rand minor versions, so seeded lattices only match while rand stays on 0.9 (float math permitting).
+*/
+#[cfg(not(feature = "reproducible"))]
+pub type SimRng = rand::rngs::SmallRng;
+#[cfg(feature = "reproducible")]
+pub type SimRng = rand_chacha::ChaCha8Rng;
 /*
 matrix_ops submodule
 */
 
 /**/
 pub fn multiply<const N: usize>(a: &[[f64; N]; N], b: &[[f64; N]; N]) -> [[f64; N]; N] {
-    todo!();
+    let mut out = [[0.0; N]; N];
+    for (out_row, a_row) in out.iter_mut().zip(a.iter()) {
+        for (k, &a_ik) in a_row.iter().enumerate() {
+            if a_ik == 0.0 {
+                continue;
+            }
+            for (o, &b_kj) in out_row.iter_mut().zip(b[k].iter()) {
+                *o += a_ik * b_kj;
+            }
+        }
+    }
+    out
 }
 
-/**/
+/*
+exp(a·t) by scaling and squaring:
+scale a·t down until its max-row-sum norm is ≤ 0.5, sum `terms` Taylor terms, square back up.
+*/
 pub fn exponential<const N: usize>(a: &[[f64; N]; N], t: f64, terms: usize) -> [[f64; N]; N] {
-    todo!();
+    let norm = a
+        .iter()
+        .map(|row| row.iter().map(|x| (x * t).abs()).sum::<f64>())
+        .fold(0.0, f64::max);
+    let mut squarings = 0;
+    while norm / 2f64.powi(squarings) > 0.5 && squarings < 64 {
+        squarings += 1;
+    }
+    let scale = t / 2f64.powi(squarings);
+
+    let mut scaled = [[0.0; N]; N];
+    for (s_row, a_row) in scaled.iter_mut().zip(a.iter()) {
+        for (s, &x) in s_row.iter_mut().zip(a_row.iter()) {
+            *s = x * scale;
+        }
+    }
+
+    let mut result = identity::<N>();
+    let mut term = identity::<N>();
+    for k in 1..=terms {
+        term = multiply(&term, &scaled);
+        for row in term.iter_mut() {
+            for x in row.iter_mut() {
+                *x /= k as f64;
+            }
+        }
+        for (r_row, t_row) in result.iter_mut().zip(term.iter()) {
+            for (r, &x) in r_row.iter_mut().zip(t_row.iter()) {
+                *r += x;
+            }
+        }
+    }
+
+    for _ in 0..squarings {
+        result = multiply(&result, &result);
+    }
+    result
+}
+
+/**/
+pub fn identity<const N: usize>() -> [[f64; N]; N] {
+    let mut out = [[0.0; N]; N];
+    for (i, row) in out.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    out
+}
+
+/**/
+pub fn apply<const N: usize>(a: &[[f64; N]; N], v: &[f64; N]) -> [f64; N] {
+    let mut out = [0.0; N];
+    for (o, row) in out.iter_mut().zip(a.iter()) {
+        *o = row.iter().zip(v.iter()).map(|(x, y)| x * y).sum();
+    }
+    out
 }
 
 /**/
@@ -32,19 +116,103 @@ pub fn eigenvectors<const N: usize>(a: &[[f64; N]; N]) -> (Vec<Complex64>, Vec<[
     todo!();
 }
 
-/**/
+/*
+|a[i][j] + a[j][i]| ≤ tol for every pair, diagonal included.
+*/
 pub fn is_antisymmetric<const N: usize>(a: &[[f64; N]; N], tol: f64) -> bool {
-    todo!();
+    (0..N).all(|i| (i..N).all(|j| (a[i][j] + a[j][i]).abs() <= tol))
 }
 
-/**/
+/*
+0.0 if col is out of range.
+*/
 pub fn column_sum<const N: usize>(a: &[[f64; N]; N], col: usize) -> f64 {
-    todo!();
+    if col >= N {
+        return 0.0;
+    }
+    a.iter().map(|row| row[col]).sum()
 }
 
-/**/
+/*
+0.0 if row is out of range.
+*/
 pub fn row_sum<const N: usize>(a: &[[f64; N]; N], row: usize) -> f64 {
-    todo!();
+    a.get(row).map_or(0.0, |r| r.iter().sum())
+}
+
+/*
+Thin wrapper over [[f64; N]; N] for whole-matrix properties.
+Converts to and from the raw array by value (From / .0) at no cost.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SquareMatrix<const N: usize>(pub [[f64; N]; N]);
+
+/**/
+impl<const N: usize> From<[[f64; N]; N]> for SquareMatrix<N> {
+    fn from(a: [[f64; N]; N]) -> Self {
+        SquareMatrix(a)
+    }
+}
+
+/**/
+impl<const N: usize> From<SquareMatrix<N>> for [[f64; N]; N] {
+    fn from(m: SquareMatrix<N>) -> Self {
+        m.0
+    }
+}
+
+/**/
+impl<const N: usize> SquareMatrix<N> {
+    /**/
+    pub fn trace(&self) -> f64 {
+        (0..N).map(|i| self.0[i][i]).sum()
+    }
+
+    /*
+    Gaussian elimination with partial pivoting.
+    */
+    pub fn determinant(&self) -> f64 {
+        let mut m = self.0;
+        let mut det = 1.0;
+        for col in 0..N {
+            let pivot = (col..N)
+                .max_by(|&i, &j| m[i][col].abs().total_cmp(&m[j][col].abs()))
+                .unwrap_or(col);
+            if m[pivot][col] == 0.0 {
+                return 0.0;
+            }
+            if pivot != col {
+                m.swap(pivot, col);
+                det = -det;
+            }
+            det *= m[col][col];
+            let (upper, lower) = m.split_at_mut(col + 1);
+            let pivot_row = &upper[col];
+            for row in lower.iter_mut() {
+                let f = row[col] / pivot_row[col];
+                for (x, &p) in row[col..].iter_mut().zip(&pivot_row[col..]) {
+                    *x -= f * p;
+                }
+            }
+        }
+        det
+    }
+
+    /**/
+    pub fn transpose(&self) -> SquareMatrix<N> {
+        let mut out = [[0.0; N]; N];
+        for (i, row) in self.0.iter().enumerate() {
+            for (j, &x) in row.iter().enumerate() {
+                out[j][i] = x;
+            }
+        }
+        SquareMatrix(out)
+    }
+
+    /**/
+    pub fn frobenius_norm(&self) -> f64 {
+        self.0.iter().flatten().map(|x| x * x).sum::<f64>().sqrt()
+    }
 }
 
 /*
@@ -52,44 +220,327 @@ sampling submodule
 */
 
 /**/
-pub fn sample_simplex(n: usize, rng: &mut SmallRng) -> Vec<f64> {
+pub fn sample_simplex(n: usize, rng: &mut SimRng) -> Vec<f64> {
     todo!();
 }
 
 /**/
-pub fn sample_normal(mean: f64, std: f64, rng: &mut SmallRng) -> f64 {
+pub fn sample_normal(mean: f64, std: f64, rng: &mut SimRng) -> f64 {
     todo!();
 }
 
 /**/
-pub fn add_noise(value: f64, noise_fraction: f64, rng: &mut SmallRng) -> f64 {
-    todo!();
+pub fn add_noise(value: f64, noise_fraction: f64, rng: &mut SimRng) -> f64 {
+    value * (1.0 + noise_fraction * rng.random_range(-1.0..=1.0))
 }
 
 /*
 fft submodule
 */
 
-/**/
+/*
+Unnormalized forward DFT, X_k = sum_n x_n exp(-2πi kn/N).
+Radix-2 for powers of two, direct O(N²) sum otherwise.
+*/
 pub fn fft_1d(signal: &[f64]) -> Vec<Complex64> {
-    todo!();
+    let mut data: Vec<Complex64> = signal.iter().map(|&x| Complex64::new(x, 0.0)).collect();
+    fft_in_place(&mut data, false);
+    data
 }
 
-/**/
+/*
+Complex DFT in place; inverse flips the exponent sign but does not scale.
+*/
+fn fft_in_place(data: &mut [Complex64], inverse: bool) {
+    let n = data.len();
+    if n <= 1 {
+        return;
+    }
+    let sign = if inverse { 1.0 } else { -1.0 };
+    if !n.is_power_of_two() {
+        let input = data.to_vec();
+        for (k, out) in data.iter_mut().enumerate() {
+            *out = input
+                .iter()
+                .enumerate()
+                .map(|(j, &x)| {
+                    let angle = sign * 2.0 * std::f64::consts::PI * ((k * j) % n) as f64 / n as f64;
+                    x * Complex64::from_polar(1.0, angle)
+                })
+                .sum();
+        }
+        return;
+    }
+
+    // Bit-reversal permutation.
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let w = Complex64::from_polar(1.0, sign * 2.0 * std::f64::consts::PI / len as f64);
+        for start in (0..n).step_by(len) {
+            let mut twiddle = Complex64::new(1.0, 0.0);
+            for k in 0..len / 2 {
+                let even = data[start + k];
+                let odd = data[start + k + len / 2] * twiddle;
+                data[start + k] = even + odd;
+                data[start + k + len / 2] = even - odd;
+                twiddle *= w;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/*
+Unnormalized 3D DFT of channel e[var_i][force_f], in lattice index order.
+Each axis is transformed with its own length, so non-cubic lattices are fine.
+Parseval: Σ|x|² = (1 / (N_x·N_y·N_z)) · Σ|X_k|², with the cell count, not any single axis length.
+Empty if the channel is out of range.
+*/
 pub fn fft_3d(lattice: &Lattice, var_i: usize, force_f: usize) -> Vec<Complex64> {
-    todo!();
+    if var_i >= VARS || force_f >= FORCES {
+        return vec![];
+    }
+    let mut data: Vec<Complex64> = lattice
+        .iter_cells()
+        .map(|(_, cell)| Complex64::new(cell.e[var_i][force_f], 0.0))
+        .collect();
+    for axis in 0..3 {
+        transform_axis(&mut data, lattice.size(), axis, false);
+    }
+    data
+}
+
+/*
+1D DFT along every line of one axis of a flat x-fastest volume.
+Lines are gathered, transformed independently (across rayon threads with the parallel feature), and scattered back,
+so serial and parallel builds give bit-identical results.
+*/
+fn transform_axis(data: &mut [Complex64], size: (usize, usize, usize), axis: usize, inverse: bool) {
+    let (sx, sy, sz) = size;
+    let (len, stride) = match axis {
+        0 => (sx, 1),
+        1 => (sy, sx),
+        _ => (sz, sx * sy),
+    };
+    if len <= 1 {
+        return;
+    }
+    // A line starts wherever this axis' coordinate is 0.
+    let starts: Vec<usize> = (0..data.len()).filter(|start| (start / stride) % len == 0).collect();
+    let mut lines: Vec<Vec<Complex64>> = starts
+        .iter()
+        .map(|&start| (0..len).map(|k| data[start + k * stride]).collect())
+        .collect();
+    #[cfg(feature = "parallel")]
+    lines.par_iter_mut().for_each(|line| fft_in_place(line, inverse));
+    #[cfg(not(feature = "parallel"))]
+    lines.iter_mut().for_each(|line| fft_in_place(line, inverse));
+    for (&start, line) in starts.iter().zip(&lines) {
+        for (k, value) in line.iter().enumerate() {
+            data[start + k * stride] = *value;
+        }
+    }
+}
+
+/*
+Inverse of fft_1d: x_n = (1/N) sum_k X_k exp(+2πi kn/N).
+The forward transform is unnormalized and the inverse carries the full 1/N, so ifft_1d(fft_1d(x)) == x.
+*/
+pub fn ifft_1d(spectrum: &[Complex64]) -> Vec<Complex64> {
+    let mut data = spectrum.to_vec();
+    fft_in_place(&mut data, true);
+    let scale = 1.0 / data.len().max(1) as f64;
+    data.iter().map(|&c| c * scale).collect()
+}
+
+/*
+Inverse of fft_3d for a spectrum in lattice index order of the given size, with 1/(sx·sy·sz) normalization.
+Empty if the spectrum length does not match size.
+*/
+pub fn ifft_3d(spectrum: &[Complex64], size: (usize, usize, usize)) -> Vec<Complex64> {
+    let (sx, sy, sz) = size;
+    if spectrum.len() != sx * sy * sz {
+        return vec![];
+    }
+    let mut data = spectrum.to_vec();
+    for axis in 0..3 {
+        transform_axis(&mut data, size, axis, true);
+    }
+    let scale = 1.0 / data.len().max(1) as f64;
+    data.iter().map(|&c| c * scale).collect()
+}
+
+/*
+Taper applied to a finite record before a DFT.
+Hann and Hamming widen the main lobe but suppress the sidelobes a rectangular (no) window leaks into.
+*/
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    #[default]
+    Hann,
+    Hamming,
 }
 
 /**/
+impl Window {
+    /*
+    Periodic window of length n (w[k] = a − (1 − a)·cos(2πk/n)), the DFT-even form.
+    */
+    pub fn coefficients(self, n: usize) -> Vec<f64> {
+        let a = match self {
+            Window::Rectangular => return vec![1.0; n],
+            Window::Hann => 0.5,
+            Window::Hamming => 0.54,
+        };
+        (0..n)
+            .map(|k| a - (1.0 - a) * (2.0 * std::f64::consts::PI * k as f64 / n as f64).cos())
+            .collect()
+    }
+}
+
+/*
+|X_k|² per bin.
+*/
 pub fn power_spectrum(fft: &[Complex64]) -> Vec<f64> {
-    todo!();
+    fft.iter().map(|c| c.norm_sqr()).collect()
 }
 
 /*
 hilbert submodule
 */
 
-/**/
+/*
+Analytic signal x + i·H[x], built by zeroing negative frequencies in the spectrum.
+*/
+pub fn analytic_signal(signal: &[f64]) -> Vec<Complex64> {
+    let n = signal.len();
+    let mut spectrum = fft_1d(signal);
+    if n == 0 {
+        return spectrum;
+    }
+    // Keep DC (and Nyquist for even n), double positive frequencies, drop negative ones.
+    let half = n / 2;
+    for (k, value) in spectrum.iter_mut().enumerate() {
+        let weight = if k == 0 || (n.is_multiple_of(2) && k == half) {
+            1.0
+        } else if k <= (n - 1) / 2 {
+            2.0
+        } else {
+            0.0
+        };
+        *value *= weight;
+    }
+    ifft_1d(&spectrum)
+}
+
+/*
+Phase of the analytic signal, in (-π, π].
+*/
 pub fn instantaneous_phase(signal: &[f64]) -> Vec<f64> {
-    todo!();
-}
\ No newline at end of file
+    analytic_signal(signal).iter().map(|c| c.arg()).collect()
+}
+
+/*
+Runs f in a fresh rayon pool of the given size, for tests comparing results across thread counts.
+*/
+#[cfg(all(test, feature = "parallel"))]
+pub(crate) fn with_threads<R: Send>(threads: usize, f: impl FnOnce() -> R + Send) -> R {
+    rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap().install(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::LatticeCoord;
+
+    #[test]
+    fn trace_and_determinant_of_known_matrices() {
+        let m = SquareMatrix([[2.0, 1.0, 0.0], [1.0, 3.0, 4.0], [0.0, 5.0, 6.0]]);
+        assert_eq!(m.trace(), 11.0);
+        assert!((m.determinant() - -10.0).abs() < 1e-12);
+        assert!((SquareMatrix([[0.0, 1.0], [1.0, 0.0]]).determinant() + 1.0).abs() < 1e-12);
+        assert!(SquareMatrix([[1.0, 2.0], [2.0, 4.0]]).determinant().abs() < 1e-12);
+        assert_eq!(SquareMatrix::<4>(identity()).determinant(), 1.0);
+        assert_eq!(m.transpose().transpose(), m);
+    }
+
+    fn fft_test_lattice(size: (usize, usize, usize)) -> Lattice {
+        let mut lattice = Lattice::new(size).unwrap();
+        for (c, cell) in lattice.iter_cells_mut() {
+            cell.e[1][2] = ((c.x * 7 + c.y * 3 + c.z * 11) % 5) as f64 * 0.3 + (c.x * c.y) as f64;
+        }
+        lattice
+    }
+
+    #[test]
+    fn fft_3d_matches_a_serial_direct_dft() {
+        let lattice = fft_test_lattice((4, 3, 6));
+        let (sx, sy, sz) = lattice.size();
+        let spectrum = fft_3d(&lattice, 1, 2);
+        for (k, value) in spectrum.iter().enumerate() {
+            let LatticeCoord { x: kx, y: ky, z: kz } = lattice.coord(k as u128).unwrap();
+            let direct: Complex64 = lattice
+                .iter_cells()
+                .map(|(c, cell)| {
+                    let phase = kx as f64 * c.x as f64 / sx as f64 + ky as f64 * c.y as f64 / sy as f64 + kz as f64 * c.z as f64 / sz as f64;
+                    cell.e[1][2] * Complex64::from_polar(1.0, -std::f64::consts::TAU * phase)
+                })
+                .sum();
+            assert!((direct - value).norm() < 1e-9, "bin {k}");
+        }
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn fft_3d_is_bit_identical_across_thread_counts() {
+        let lattice = fft_test_lattice((8, 6, 5));
+        let on = |threads| with_threads(threads, || fft_3d(&lattice, 1, 2));
+        let serial = on(1);
+        assert_eq!(serial, on(4));
+        assert_eq!(serial, fft_3d(&lattice, 1, 2));
+    }
+
+    #[test]
+    fn inverse_fft_recovers_the_input() {
+        for n in [1, 2, 3, 5, 8, 12, 16, 31] {
+            let signal: Vec<f64> = (0..n).map(|i| ((i * 37 % 11) as f64).sin() + i as f64 * 0.1).collect();
+            let recovered = ifft_1d(&fft_1d(&signal));
+            for (x, y) in signal.iter().zip(&recovered) {
+                assert!((x - y.re).abs() < 1e-10 && y.im.abs() < 1e-10, "n = {n}");
+            }
+        }
+        let lattice = fft_test_lattice((4, 3, 5));
+        let recovered = ifft_3d(&fft_3d(&lattice, 1, 2), lattice.size());
+        for ((_, cell), y) in lattice.iter_cells().zip(&recovered) {
+            assert!((cell.e[1][2] - y.re).abs() < 1e-10);
+        }
+        assert!(ifft_3d(&recovered, (2, 2, 2)).is_empty());
+    }
+
+    #[test]
+    fn row_and_column_sums_are_zero_out_of_range() {
+        let a = [[1.0, 2.0], [3.0, 4.0]];
+        assert_eq!(column_sum(&a, 1), 6.0);
+        assert_eq!(row_sum(&a, 1), 7.0);
+        assert_eq!(column_sum(&a, 2), 0.0);
+        assert_eq!(row_sum(&a, 2), 0.0);
+    }
+
+    #[cfg(feature = "reproducible")]
+    #[test]
+    fn reproducible_rng_pins_the_raw_chacha8_stream() {
+        use rand::{RngCore, SeedableRng};
+        let mut rng = SimRng::seed_from_u64(7);
+        assert_eq!(rng.next_u64(), 0x2865533423d743bb);
+        assert_eq!(rng.next_u64(), 0x2b0159d32e9b293a);
+    }
+}
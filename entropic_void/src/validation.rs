@@ -0,0 +1,206 @@
+#![forbid(unsafe_code)]
+
+/*
+Purpose: Self-checks users can run on their own configurations.
+
+Uses Simulation from evolution, and the snapshot format from visualization.
+Uses redistribution and utils for the numerical checks in self_test.
+*/
+
+use std::fmt;
+use std::io::Cursor;
+use num_complex::Complex64;
+use crate::evolution::{NegativePolicy, Simulation};
+use crate::lattice::Lattice;
+use crate::redistribution::{self, EXPONENTIAL_TERMS};
+use crate::types::{ConstraintSet, FORCES, N_FLATTENED, RedistributionMatrix, VARS};
+use crate::utils;
+use crate::visualization::{self, SnapshotReader};
+
+/*
+One self_test invariant and the error measured for it.
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SelfTestCheck {
+    pub name: &'static str,
+    pub error: f64,
+    pub tolerance: f64,
+}
+
+/**/
+impl SelfTestCheck {
+    /*
+    error is within tolerance (false when it is NaN).
+    */
+    pub fn passed(&self) -> bool {
+        self.error <= self.tolerance
+    }
+}
+
+/*
+"name: PASS (error 1.2e-15, tolerance 1e-12)".
+*/
+impl fmt::Display for SelfTestCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = if self.passed() { "PASS" } else { "FAIL" };
+        write!(f, "{}: {verdict} (error {:.1e}, tolerance {:.0e})", self.name, self.error, self.tolerance)
+    }
+}
+
+/*
+Core numerical invariants on a small reference lattice, for checking that a build (and its float and
+thread backends) computes what it should:
+    conservation: relative energy error after 20 transport-only steps with a zero matrix;
+    reversibility: largest channel difference after 20 steps of a pure oscillation and 20 step_backs;
+    fft round trip: largest |ifft_1d(fft_1d(x)) - x| on a 64-sample signal;
+    matrix exponential: largest difference between utils::exponential of a two-mode oscillation and
+        its analytic rotation, x(t) = x·cos ωt + y·sin ωt, y(t) = y·cos ωt - x·sin ωt.
+Every check is deterministic, so results only vary with the build.
+*/
+pub fn self_test() -> Vec<SelfTestCheck> {
+    vec![
+        SelfTestCheck { name: "conservation", error: conservation_error(), tolerance: 1e-12 },
+        SelfTestCheck { name: "reversibility", error: reversibility_error(), tolerance: 1e-9 },
+        SelfTestCheck { name: "fft round trip", error: fft_round_trip_error(), tolerance: 1e-12 },
+        SelfTestCheck { name: "matrix exponential", error: exponential_error(), tolerance: 1e-10 },
+    ]
+}
+
+fn reference_simulation(redistribution: RedistributionMatrix) -> Option<Simulation> {
+    let mut lattice = Lattice::new((4, 4, 4))?;
+    for (coord, cell) in lattice.iter_cells_mut() {
+        for (i, row) in cell.e.iter_mut().enumerate() {
+            for (f, value) in row.iter_mut().enumerate() {
+                *value = 1.0 + ((coord.x + 2 * coord.y + 3 * coord.z + i * FORCES + f) % 7) as f64 / 7.0;
+            }
+        }
+    }
+    Simulation::new(lattice, redistribution, [[0.1; FORCES]; VARS], ConstraintSet::default()).ok()
+}
+
+fn conservation_error() -> f64 {
+    let Some(mut sim) = reference_simulation(RedistributionMatrix::default()) else {
+        return f64::NAN;
+    };
+    for _ in 0..20 {
+        if sim.step(0.1).is_err() {
+            return f64::NAN;
+        }
+    }
+    sim.verify_energy_conservation()
+}
+
+fn reversibility_error() -> f64 {
+    let mut matrix = RedistributionMatrix::default();
+    redistribution::set_oscillation(&mut matrix, 0, N_FLATTENED - 1, 0.5);
+    let Some(mut sim) = reference_simulation(matrix) else {
+        return f64::NAN;
+    };
+    // The rotation takes channels negative, and clamping them is not inverted by step_back.
+    sim.negative_policy = NegativePolicy::Allow;
+    let start = sim.lattice.clone();
+    for _ in 0..20 {
+        if sim.step(0.1).is_err() {
+            return f64::NAN;
+        }
+    }
+    for _ in 0..20 {
+        if sim.step_back(0.1).is_err() {
+            return f64::NAN;
+        }
+    }
+    max_difference(&start, &sim.lattice)
+}
+
+fn fft_round_trip_error() -> f64 {
+    let signal: Vec<f64> = (0..64).map(|n| (0.3 * n as f64).sin() + 0.25 * (1.7 * n as f64).cos()).collect();
+    utils::ifft_1d(&utils::fft_1d(&signal))
+        .iter()
+        .zip(&signal)
+        .fold(0.0, |m: f64, (c, &x)| m.max((c - Complex64::new(x, 0.0)).norm()))
+}
+
+fn exponential_error() -> f64 {
+    let (omega, t) = (0.8, 1.25);
+    let mut matrix = RedistributionMatrix::default();
+    redistribution::set_oscillation(&mut matrix, 0, 1, omega);
+    let mut start = [0.0; N_FLATTENED];
+    start[0] = 0.6;
+    start[1] = -0.3;
+    let evolved = utils::apply(&utils::exponential(&matrix.a, t, EXPONENTIAL_TERMS), &start);
+    let (c, s) = ((omega * t).cos(), (omega * t).sin());
+    let mut expected = start;
+    expected[0] = start[0] * c + start[1] * s;
+    expected[1] = start[1] * c - start[0] * s;
+    evolved.iter().zip(&expected).fold(0.0, |m: f64, (a, b)| m.max((a - b).abs()))
+}
+
+fn max_difference(a: &Lattice, b: &Lattice) -> f64 {
+    a.cells()
+        .iter()
+        .zip(b.cells())
+        .flat_map(|(a, b)| a.e.iter().flatten().zip(b.e.iter().flatten()))
+        .fold(0.0, |m: f64, (a, b)| m.max((a - b).abs()))
+}
+
+/*
+Checks that checkpointing does not perturb a run: steps a copy of sim `steps` times with dt from a
+saved-and-reloaded state (save → load → step), and another copy before saving and reloading (step → save → load).
+Snapshots go through the export_full_snapshot text format in memory, so no files are written.
+Returns the largest absolute channel difference between the two final lattices; 0.0 when restarts are exact.
+sim itself is not modified.
+*/
+pub fn check_restart_invariance(sim: &Simulation, dt: f64, steps: usize) -> Result<f64, String> {
+    let mut restarted = sim.clone();
+    let (time, lattice) = round_trip(&sim.lattice, sim.time)?;
+    restarted.lattice = lattice;
+    restarted.time = time;
+    let mut direct = sim.clone();
+    for _ in 0..steps {
+        restarted.step(dt)?;
+        direct.step(dt)?;
+    }
+    let (_, direct_lattice) = round_trip(&direct.lattice, direct.time)?;
+    if restarted.lattice.size() != direct_lattice.size() {
+        return Err("restarted and direct lattices differ in size".to_string());
+    }
+    Ok(max_difference(&restarted.lattice, &direct_lattice))
+}
+
+fn round_trip(lattice: &Lattice, time: f64) -> Result<(f64, Lattice), String> {
+    let mut buffer = vec![];
+    visualization::write_snapshot(&mut buffer, lattice, time).map_err(|e| e.to_string())?;
+    SnapshotReader::new(Cursor::new(buffer))
+        .next()
+        .ok_or("empty snapshot")?
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_configuration_restarts_identically() {
+        let mut matrix = RedistributionMatrix::default();
+        redistribution::set_oscillation(&mut matrix, 0, 11, 0.7);
+        let sim = reference_simulation(matrix).unwrap();
+        assert_eq!(check_restart_invariance(&sim, 0.05, 10).unwrap(), 0.0);
+        assert_eq!(sim.step, 0);
+        assert!(check_restart_invariance(&sim, -1.0, 1).is_err());
+    }
+
+    #[test]
+    fn self_test_passes_every_check() {
+        let checks = self_test();
+        assert_eq!(checks.len(), 4);
+        for check in &checks {
+            assert!(check.passed(), "{check}");
+            let line = check.to_string();
+            assert!(line.starts_with(&format!("{}: PASS (error ", check.name)), "{line}");
+        }
+        let failing = SelfTestCheck { name: "broken", error: 1.0, tolerance: 1e-12 };
+        assert!(!failing.passed());
+        assert_eq!(failing.to_string(), "broken: FAIL (error 1.0e0, tolerance 1e-12)");
+    }
+}
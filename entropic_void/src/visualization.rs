@@ -10,11 +10,178 @@ Uses utils::fft for spectrum.
 Output:
     2D grid [x][y] of total or chosen-variable energy.
 */
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use crate::conservation;
+use crate::energy;
+use crate::evolution::Simulation;
 use crate::lattice::Lattice;
-use crate::types::LatticeCoord;
+use crate::transport;
+use crate::types::{CellState, Direction, FORCES, LatticeCoord, VARS};
+use crate::utils;
 
 pub fn slice_xy(lattice: &Lattice, z_index: usize, var_i: Option<usize>) -> Vec<Vec<f64>> {
-    todo!();
+    match var_i {
+        None => plane_xy(lattice, z_index, energy::total_energy),
+        Some(v) if v < VARS => plane_xy(lattice, z_index, |cell| cell.e[v].iter().sum()),
+        Some(_) => vec![],
+    }
+}
+
+/*
+Single (var_i, force_f) channel on plane z_index, as a [x][y] grid.
+Empty if any index is out of range.
+*/
+pub fn slice_channel(lattice: &Lattice, z_index: usize, var_i: usize, force_f: usize) -> Vec<Vec<f64>> {
+    if var_i >= VARS || force_f >= FORCES {
+        return vec![];
+    }
+    plane_xy(lattice, z_index, |cell| cell.e[var_i][force_f])
+}
+
+/*
+(min, max, mean) over a slice from slice_xy / slice_channel; None if it is empty.
+*/
+pub fn slice_stats(slice: &[Vec<f64>]) -> Option<(f64, f64, f64)> {
+    let mut count = 0usize;
+    let mut sum = 0.0;
+    let mut min = f64::INFINITY;
+    let mut max = f64::NEG_INFINITY;
+    for &value in slice.iter().flatten() {
+        count += 1;
+        sum += value;
+        min = min.min(value);
+        max = max.max(value);
+    }
+    if count == 0 { None } else { Some((min, max, sum / count as f64)) }
+}
+
+/*
+(low, high) color range for a slice: its lower and upper percentiles (0–100), linearly interpolated between
+sorted values, so a few extreme cells do not stretch the range for the rest of the field (e.g. 2.0, 98.0).
+Values outside the range are meant to be clamped to it when normalizing. Non-finite values are skipped.
+0.0, 100.0 gives slice_stats' (min, max).
+None if no finite values remain or the percentiles are not 0 ≤ lower ≤ upper ≤ 100.
+*/
+pub fn slice_percentile_range(slice: &[Vec<f64>], lower: f64, upper: f64) -> Option<(f64, f64)> {
+    if !(0.0..=100.0).contains(&lower) || !(lower..=100.0).contains(&upper) {
+        return None;
+    }
+    let mut values: Vec<f64> = slice.iter().flatten().copied().filter(|v| v.is_finite()).collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let at = |p: f64| {
+        let rank = p / 100.0 * (values.len() - 1) as f64;
+        let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+        values[below] + (values[above] - values[below]) * (rank - below as f64)
+    };
+    Some((at(lower), at(upper)))
+}
+
+/*
+A slice block-averaged to fit a pane, see fit_slice.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct FittedSlice {
+    pub grid: Vec<Vec<f64>>,
+    // Cells per block along x and y.
+    pub factor: (usize, usize),
+}
+
+/*
+Shrink a [x][y] slice to at most width × height for a display pane by block-averaging,
+mapping cell (x, y) to block (x / f_x, y / f_y) like Lattice::coarse_grain.
+Each axis gets its own factor f = ceil(n / limit) (1 when it already fits), so a pane smaller than the lattice
+in only one dimension scales that one only. Edge blocks average the cells they have.
+None for a zero-sized pane.
+*/
+pub fn fit_slice(slice: &[Vec<f64>], width: usize, height: usize) -> Option<FittedSlice> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let nx = slice.len();
+    let ny = slice.iter().map(Vec::len).max().unwrap_or(0);
+    let (fx, fy) = (nx.div_ceil(width).max(1), ny.div_ceil(height).max(1));
+    let mut sums = vec![vec![(0.0, 0usize); ny.div_ceil(fy)]; nx.div_ceil(fx)];
+    for (x, column) in slice.iter().enumerate() {
+        for (y, &value) in column.iter().enumerate() {
+            let block = &mut sums[x / fx][y / fy];
+            block.0 += value;
+            block.1 += 1;
+        }
+    }
+    let grid = sums
+        .into_iter()
+        .map(|column| column.into_iter().map(|(sum, n)| if n > 0 { sum / n as f64 } else { 0.0 }).collect())
+        .collect();
+    Some(FittedSlice { grid, factor: (fx, fy) })
+}
+
+/*
+Signed change of total energy per cell since construction (current − sim.initial_density), in index order,
+for a diverging colormap. Empty if the lattice no longer has as many cells as initial_density.
+*/
+pub fn delta_field(sim: &Simulation) -> Vec<f64> {
+    let current = energy_density_field(&sim.lattice);
+    if current.len() != sim.initial_density.len() {
+        return vec![];
+    }
+    current.iter().zip(sim.initial_density.iter()).map(|(now, then)| now - then).collect()
+}
+
+/*
+Population variance of total density on each z plane, indexed by z.
+*/
+pub fn slice_variances(lattice: &Lattice) -> Vec<f64> {
+    let (sx, sy, sz) = lattice.size();
+    let density = energy_density_field(lattice);
+    let plane = sx * sy;
+    (0..sz)
+        .map(|z| {
+            density[z * plane..(z + 1) * plane]
+                .iter()
+                .fold(conservation::DensityAccumulator::new(), |acc, &x| acc.push(x))
+                .variance()
+        })
+        .collect()
+}
+
+/*
+(z of the most varied plane, z of the least varied plane); lowest z wins ties.
+None for an empty lattice.
+*/
+pub fn extreme_variance_slices(lattice: &Lattice) -> Option<(usize, usize)> {
+    let variances = slice_variances(lattice);
+    if variances.is_empty() {
+        return None;
+    }
+    let mut hi = 0;
+    let mut lo = 0;
+    for (z, &v) in variances.iter().enumerate() {
+        if v > variances[hi] {
+            hi = z;
+        }
+        if v < variances[lo] {
+            lo = z;
+        }
+    }
+    Some((hi, lo))
+}
+
+fn plane_xy(lattice: &Lattice, z_index: usize, value: impl Fn(&CellState) -> f64) -> Vec<Vec<f64>> {
+    let (sx, sy, sz) = lattice.size();
+    if z_index >= sz {
+        return vec![];
+    }
+    (0..sx)
+        .map(|x| {
+            (0..sy)
+                .map(|y| lattice.at(LatticeCoord { x, y, z: z_index }).map_or(0.0, &value))
+                .collect()
+        })
+        .collect()
 }
 
 /**/
@@ -22,39 +189,435 @@ pub fn slice_along_axis(lattice: &Lattice, axis: usize, index: usize, var_i: Opt
     todo!();
 }
 
+/*
+Column-integrated map: energy (total, or variable var_i's) summed along axis (0 = x, 1 = y, 2 = z),
+as a [a][b] grid over the two remaining axes in increasing order, so axis 2 gives [x][y] like slice_xy.
+Empty for an invalid axis or variable.
+*/
+pub fn projection(lattice: &Lattice, axis: usize, var_i: Option<usize>) -> Vec<Vec<f64>> {
+    if axis > 2 || var_i.is_some_and(|v| v >= VARS) {
+        return vec![];
+    }
+    let (sx, sy, sz) = lattice.size();
+    let (a_len, b_len) = match axis {
+        0 => (sy, sz),
+        1 => (sx, sz),
+        _ => (sx, sy),
+    };
+    let mut grid = vec![vec![0.0; b_len]; a_len];
+    for (coord, cell) in lattice.iter_cells() {
+        let (a, b) = match axis {
+            0 => (coord.y, coord.z),
+            1 => (coord.x, coord.z),
+            _ => (coord.x, coord.y),
+        };
+        grid[a][b] += match var_i {
+            None => energy::total_energy(cell),
+            Some(v) => cell.e[v].iter().sum(),
+        };
+    }
+    grid
+}
+
+/*
+Total-energy planes perpendicular to axis (0 = x, 1 = y, 2 = z), one at a time in increasing coordinate.
+Each plane is flattened with the lower remaining axis fastest, matching lattice index order,
+so for axis 2 the planes concatenate to energy_density_field. Yields nothing for an invalid axis.
+*/
+pub fn iter_slices(lattice: &Lattice, axis: usize) -> impl Iterator<Item = Vec<f64>> + '_ {
+    let (sx, sy, sz) = lattice.size();
+    let (planes, a_len, b_len) = match axis {
+        0 => (sx, sy, sz),
+        1 => (sy, sx, sz),
+        2 => (sz, sx, sy),
+        _ => (0, 0, 0),
+    };
+    (0..planes).map(move |p| {
+        let mut plane = Vec::with_capacity(a_len * b_len);
+        for b in 0..b_len {
+            for a in 0..a_len {
+                let coord = match axis {
+                    0 => LatticeCoord { x: p, y: a, z: b },
+                    1 => LatticeCoord { x: a, y: p, z: b },
+                    _ => LatticeCoord { x: a, y: b, z: p },
+                };
+                plane.push(lattice.at(coord).map_or(0.0, energy::total_energy));
+            }
+        }
+        plane
+    })
+}
+
 /*
 Uses utils::fft::fft_3d → power spectrum.
+Shell-averaged P(k) as (k, mean |X|²) pairs, k ascending, DC excluded.
+k = 2π·|(k_x/N_x, k_y/N_y, k_z/N_z)| in radians per cell, shells 2π / max(N) wide.
 */
 pub fn volume_fft(lattice: &Lattice, var_i: usize, force_f: usize) -> Vec<(f64, f64)> {
-    todo!();
+    let spectrum = utils::fft_3d(lattice, var_i, force_f);
+    if spectrum.is_empty() {
+        return vec![];
+    }
+    let power = utils::power_spectrum(&spectrum);
+    let (sx, sy, sz) = lattice.size();
+    let shell_width = 2.0 * std::f64::consts::PI / sx.max(sy).max(sz) as f64;
+    let mut shells: Vec<(f64, usize)> = vec![];
+    for ((coord, _), p) in lattice.iter_cells().zip(power.iter()) {
+        let k = transport::fourier_mode_frequency(
+            (signed_bin(coord.x, sx), signed_bin(coord.y, sy), signed_bin(coord.z, sz)),
+            (sx, sy, sz),
+        );
+        let shell = (k / shell_width).round() as usize;
+        if shell == 0 {
+            continue;
+        }
+        if shells.len() <= shell {
+            shells.resize(shell + 1, (0.0, 0));
+        }
+        shells[shell].0 += p;
+        shells[shell].1 += 1;
+    }
+    shells
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, count))| *count > 0)
+        .map(|(shell, (sum, count))| (shell as f64 * shell_width, sum / *count as f64))
+        .collect()
+}
+
+/*
+Signed mode index of FFT bin i on an axis of length n, folding bins above n/2 to negative.
+*/
+fn signed_bin(i: usize, n: usize) -> isize {
+    if i <= n / 2 { i as isize } else { i as isize - n as isize }
+}
+
+/*
+Reduce a (k, P) spectrum to n_bins log-spaced k bins, returned as (log10 k, log10 mean P) for plotting.
+Points with k ≤ 0 or P ≤ 0 and empty bins are dropped.
+*/
+pub fn log_binned_spectrum(spectrum: &[(f64, f64)], n_bins: usize) -> Vec<(f64, f64)> {
+    let points: Vec<(f64, f64)> = spectrum
+        .iter()
+        .filter(|(k, p)| *k > 0.0 && *p > 0.0)
+        .map(|(k, p)| (k.log10(), *p))
+        .collect();
+    if points.is_empty() || n_bins == 0 {
+        return vec![];
+    }
+    let lo = points.iter().map(|p| p.0).fold(f64::INFINITY, f64::min);
+    let hi = points.iter().map(|p| p.0).fold(f64::NEG_INFINITY, f64::max);
+    let width = (hi - lo) / n_bins as f64;
+    let mut bins = vec![(0.0, 0.0, 0usize); n_bins];
+    for (log_k, p) in points {
+        let bin = if width > 0.0 { (((log_k - lo) / width) as usize).min(n_bins - 1) } else { 0 };
+        bins[bin].0 += log_k;
+        bins[bin].1 += p;
+        bins[bin].2 += 1;
+    }
+    bins.iter()
+        .filter(|b| b.2 > 0)
+        .map(|(sum_k, sum_p, count)| (sum_k / *count as f64, (sum_p / *count as f64).log10()))
+        .collect()
 }
 
 /*
 Flat vector of per-cell total energy.
 */
 pub fn energy_density_field(lattice: &Lattice) -> Vec<f64> {
-    todo!();
+    lattice.iter_cells().map(|(_, cell)| energy::total_energy(cell)).collect()
+}
+
+/*
+Flat vector of per-cell energy::effective_temperature.
+*/
+pub fn temperature_field(lattice: &Lattice) -> Vec<f64> {
+    lattice.iter_cells().map(|(_, cell)| energy::effective_temperature(cell)).collect()
+}
+
+/*
+(total energy, |∇ρ|) per cell in index order, ρ being total energy.
+Each gradient component is the periodic central difference (ρ(c + 1) − ρ(c − 1)) / 2 along that axis.
+*/
+pub fn phase_space(lattice: &Lattice) -> Vec<(f64, f64)> {
+    let density = energy_density_field(lattice);
+    let at = |c: LatticeCoord| lattice.index(c).map_or(0.0, |i| density[i as usize]);
+    lattice
+        .iter_cells()
+        .zip(density.iter())
+        .map(|((coord, _), &rho)| {
+            let gradient_sq: f64 = [Direction::PosX, Direction::PosY, Direction::PosZ]
+                .iter()
+                .map(|&d| {
+                    let forward = lattice.shifted(&coord, d.offset());
+                    let backward = lattice.shifted(&coord, d.opposite().offset());
+                    let g = 0.5 * (at(forward) - at(backward));
+                    g * g
+                })
+                .sum();
+            (rho, gradient_sq.sqrt())
+        })
+        .collect()
+}
+
+/*
+Net energy flux per cell in index order, as (x, y, z) energy moved per step of length dt.
+Each bond's flow is transport::bond_transfer (the exchange distribute_to_neighbors would make, from the current state),
+and component d averages the flows across the cell's -d and +d bonds, positive along +d.
+Flux points down the density gradient.
+*/
+pub fn flux_field(lattice: &Lattice, coupling: &[[f64; FORCES]; VARS], dt: f64) -> Vec<(f64, f64, f64)> {
+    lattice
+        .iter_cells()
+        .map(|(coord, cell)| {
+            let component = |d: Direction| {
+                let forward = lattice.at(lattice.shifted(&coord, d.offset()));
+                let backward = lattice.at(lattice.shifted(&coord, d.opposite().offset()));
+                match (forward, backward) {
+                    (Some(f), Some(b)) => {
+                        0.5 * (transport::bond_transfer(cell, f, coupling, dt) + transport::bond_transfer(b, cell, coupling, dt))
+                    }
+                    _ => 0.0,
+                }
+            };
+            (component(Direction::PosX), component(Direction::PosY), component(Direction::PosZ))
+        })
+        .collect()
 }
 
 /*
 For each cell, index of variable with max energy.
+Ties go to the lowest variable index, so an all-equal cell maps to 0.
 */
 pub fn variable_dominance_map(lattice: &Lattice) -> Vec<usize> {
-    todo!();
+    lattice
+        .iter_cells()
+        .map(|(_, cell)| {
+            let totals = energy::per_variable(cell);
+            let mut best = 0;
+            for (i, &total) in totals.iter().enumerate().skip(1) {
+                if total > totals[best] {
+                    best = i;
+                }
+            }
+            best
+        })
+        .collect()
+}
+
+/*
+For each cell, the (var, force) of its largest channel.
+Ties go to the lowest flat index (var first, then force), so an all-equal cell maps to (0, 0).
+*/
+pub fn dominant_channel_map(lattice: &Lattice) -> Vec<(usize, usize)> {
+    lattice
+        .iter_cells()
+        .map(|(_, cell)| {
+            let mut best = (0, 0);
+            for (var, row) in cell.e.iter().enumerate() {
+                for (force, &value) in row.iter().enumerate() {
+                    if value > cell.e[best.0][best.1] {
+                        best = (var, force);
+                    }
+                }
+            }
+            best
+        })
+        .collect()
+}
+
+/*
+variable_dominance_map for one z plane, as a [x][y] grid like slice_xy.
+Empty if z_index is out of range.
+*/
+pub fn variable_dominance_slice(lattice: &Lattice, z_index: usize) -> Vec<Vec<usize>> {
+    let (sx, sy, sz) = lattice.size();
+    if z_index >= sz {
+        return vec![];
+    }
+    let map = variable_dominance_map(lattice);
+    let layer = z_index * sx * sy;
+    (0..sx)
+        .map(|x| (0..sy).map(|y| map[layer + y * sx + x]).collect())
+        .collect()
 }
 
 /*
 JSON or CSV-like representation.
+One-line JSON object {"x":..,"y":..,"z":..,"e":[[..FORCES..]; VARS]}; "null" if coord is out of bounds.
 */
 pub fn export_cell_state(lattice: &Lattice, coord: LatticeCoord) -> String {
-    todo!();
+    let Some(cell) = lattice.at(coord) else {
+        return "null".to_string();
+    };
+    let rows: Vec<String> = cell
+        .e
+        .iter()
+        .map(|row| format!("[{}]", row.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",")))
+        .collect();
+    format!("{{\"x\":{},\"y\":{},\"z\":{},\"e\":[{}]}}", coord.x, coord.y, coord.z, rows.join(","))
 }
 
 /*
 Write lattice energies to disk.
+Format: a "# time=<t> size=<sx>,<sy>,<sz>" line, a CSV header, then one "x,y,z,e[0][0],..,e[VARS-1][FORCES-1]" row
+per cell in index order. Values are written exactly (shortest round-trip form).
+A filename ending in ".gz" is gzip-compressed while streaming; that needs the flate2 feature.
 */
-pub fn export_full_snapshot(lattice: &Lattice, time: f64, filename: &str) {
-    todo!();
+pub fn export_full_snapshot(lattice: &Lattice, time: f64, filename: &str) -> io::Result<()> {
+    save_snapshot(lattice, time, filename, File::options().write(true).create(true).truncate(true))
+}
+
+/*
+Like export_full_snapshot, but appends to filename (creating it if needed), building a time series
+that SnapshotReader reads back one snapshot at a time. Appending to ".gz" adds a gzip member.
+*/
+pub fn append_full_snapshot(lattice: &Lattice, time: f64, filename: &str) -> io::Result<()> {
+    save_snapshot(lattice, time, filename, File::options().append(true).create(true))
+}
+
+// Refuses ".gz" without the flate2 feature before opening, so an existing file is never truncated for nothing.
+fn save_snapshot(lattice: &Lattice, time: f64, filename: &str, options: &fs::OpenOptions) -> io::Result<()> {
+    let gzip = filename.ends_with(".gz");
+    #[cfg(not(feature = "flate2"))]
+    if gzip {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "gzip snapshots need the flate2 feature"));
+    }
+    let mut file = BufWriter::new(options.open(filename)?);
+    #[cfg(feature = "flate2")]
+    if gzip {
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        write_snapshot(&mut encoder, lattice, time)?;
+        return encoder.finish()?.flush();
+    }
+    write_snapshot(&mut file, lattice, time)?;
+    file.flush()
+}
+
+/*
+Reads a file written by export_full_snapshot (gzip when the name ends in ".gz") back into (time, lattice).
+For a file of several snapshots this is the first; use SnapshotReader for the rest.
+*/
+pub fn load_full_snapshot(filename: &str) -> io::Result<(f64, Lattice)> {
+    SnapshotReader::open(filename)?
+        .next()
+        .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::InvalidData, "empty snapshot")))
+}
+
+/*
+Lazily reads the snapshots of a file written by export_full_snapshot / append_full_snapshot,
+yielding (time, lattice) in file order with only one lattice in memory at a time.
+Iteration stops after the first error.
+*/
+pub struct SnapshotReader {
+    lines: io::Lines<Box<dyn BufRead>>,
+    // Metadata line of the next snapshot, read while finishing the previous one.
+    pending: Option<String>,
+    failed: bool,
+}
+
+/**/
+impl SnapshotReader {
+    /*
+    Opens filename, decompressing (all gzip members) when it ends in ".gz"; that needs the flate2 feature.
+    */
+    pub fn open(filename: &str) -> io::Result<SnapshotReader> {
+        let file = BufReader::new(File::open(filename)?);
+        if filename.ends_with(".gz") {
+            #[cfg(feature = "flate2")]
+            return Ok(SnapshotReader::new(BufReader::new(flate2::read::MultiGzDecoder::new(file))));
+            #[cfg(not(feature = "flate2"))]
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "gzip snapshots need the flate2 feature"));
+        }
+        Ok(SnapshotReader::new(file))
+    }
+
+    /**/
+    pub fn new(reader: impl BufRead + 'static) -> SnapshotReader {
+        let reader: Box<dyn BufRead> = Box::new(reader);
+        SnapshotReader { lines: reader.lines(), pending: None, failed: false }
+    }
+
+    fn read_next(&mut self) -> io::Result<Option<(f64, Lattice)>> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let meta = match self.pending.take() {
+            Some(meta) => meta,
+            None => loop {
+                match self.lines.next().transpose()? {
+                    None => return Ok(None),
+                    Some(line) if line.is_empty() => continue,
+                    Some(line) => break line,
+                }
+            },
+        };
+        let rest = meta.strip_prefix("# time=").ok_or_else(|| invalid("missing snapshot metadata"))?;
+        let (time, size) = rest.split_once(" size=").ok_or_else(|| invalid("missing snapshot size"))?;
+        let time: f64 = time.parse().map_err(|_| invalid("bad snapshot time"))?;
+        let dims: Vec<usize> = size
+            .split(',')
+            .map(|d| d.parse().map_err(|_| invalid("bad snapshot size")))
+            .collect::<io::Result<_>>()?;
+        let [sx, sy, sz] = dims[..] else {
+            return Err(invalid("bad snapshot size"));
+        };
+        let mut lattice = Lattice::new((sx, sy, sz)).ok_or_else(|| invalid("bad snapshot size"))?;
+        self.lines.next().ok_or_else(|| invalid("missing snapshot header"))??;
+        for line in self.lines.by_ref() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with("# time=") {
+                self.pending = Some(line);
+                break;
+            }
+            let fields: Vec<&str> = line.split(',').collect();
+            if fields.len() != 3 + VARS * FORCES {
+                return Err(invalid("wrong number of snapshot columns"));
+            }
+            let index = |i: usize| fields[i].parse::<usize>().map_err(|_| invalid("bad snapshot coordinate"));
+            let coord = LatticeCoord { x: index(0)?, y: index(1)?, z: index(2)? };
+            let cell = lattice.at_mut(coord).ok_or_else(|| invalid("snapshot coordinate out of bounds"))?;
+            for (value, field) in cell.e.iter_mut().flatten().zip(&fields[3..]) {
+                *value = field.parse().map_err(|_| invalid("bad snapshot value"))?;
+            }
+        }
+        Ok(Some((time, lattice)))
+    }
+}
+
+/**/
+impl Iterator for SnapshotReader {
+    type Item = io::Result<(f64, Lattice)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.read_next().transpose();
+        self.failed = matches!(result, Some(Err(_)));
+        result
+    }
+}
+
+pub(crate) fn write_snapshot(w: &mut impl Write, lattice: &Lattice, time: f64) -> io::Result<()> {
+    let (sx, sy, sz) = lattice.size();
+    writeln!(w, "# time={time} size={sx},{sy},{sz}")?;
+    let mut header = String::from("x,y,z");
+    for v in 0..VARS {
+        for f in 0..FORCES {
+            header.push_str(&format!(",e{v}_{f}"));
+        }
+    }
+    writeln!(w, "{header}")?;
+    for (coord, cell) in lattice.iter_cells() {
+        write!(w, "{},{},{}", coord.x, coord.y, coord.z)?;
+        for value in cell.e.iter().flatten() {
+            write!(w, ",{value}")?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
 }
 
 /*
@@ -66,7 +629,340 @@ pub fn isosurface_data(lattice: &Lattice, threshold: f64) -> Vec<LatticeCoord> {
 
 /*
 Uses thresholds on density distribution (e.g., mean ± σ).
+The default conservation::ClassificationPolicy; see classify_with_policy for others.
 */
 pub fn void_wall_filament_classification(lattice: &Lattice) -> (Vec<LatticeCoord>, Vec<LatticeCoord>, Vec<LatticeCoord>) {
-    todo!();
-}
\ No newline at end of file
+    conservation::classify_with_policy(lattice, conservation::ClassificationPolicy::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{ConstraintSet, RedistributionMatrix};
+
+    #[test]
+    fn dominance_ties_go_to_the_lowest_variable() {
+        let mut lattice = Lattice::new((3, 2, 2)).unwrap();
+        for (_, cell) in lattice.iter_cells_mut() {
+            for row in cell.e.iter_mut() {
+                row[0] = 1.0;
+            }
+        }
+        lattice.at_mut(LatticeCoord { x: 2, y: 1, z: 1 }).unwrap().e[3][1] = 1.0;
+        let map = variable_dominance_map(&lattice);
+        assert_eq!(map[0], 0);
+        assert_eq!(map[11], 3);
+        let slice = variable_dominance_slice(&lattice, 1);
+        assert_eq!(slice[0][0], 0);
+        assert_eq!(slice[2][1], 3);
+        assert!(variable_dominance_slice(&lattice, 2).is_empty());
+    }
+
+    #[test]
+    fn channel_slices_sum_to_the_variable_slice() {
+        let mut lattice = Lattice::new((3, 2, 2)).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            for (f, value) in cell.e[1].iter_mut().enumerate() {
+                *value = (coord.x + 2 * coord.y + f) as f64;
+            }
+        }
+        let mut summed = vec![vec![0.0; 2]; 3];
+        for f in 0..FORCES {
+            for (column, channel) in summed.iter_mut().zip(slice_channel(&lattice, 1, 1, f)) {
+                for (total, value) in column.iter_mut().zip(channel) {
+                    *total += value;
+                }
+            }
+        }
+        assert_eq!(slice_xy(&lattice, 1, Some(1)), summed);
+        assert!(slice_channel(&lattice, 1, 1, FORCES).is_empty());
+        assert!(slice_channel(&lattice, 1, VARS, 0).is_empty());
+    }
+
+    #[test]
+    fn slice_stats_of_known_slice() {
+        assert_eq!(slice_stats(&[vec![1.0, 5.0], vec![3.0, -1.0]]), Some((-1.0, 5.0, 2.0)));
+        assert_eq!(slice_stats(&[vec![], vec![]]), None);
+        assert_eq!(slice_stats(&[]), None);
+    }
+
+    #[test]
+    fn log_binning_averages_points_per_decade() {
+        let spectrum = [(0.0, 5.0), (1.0, 1.0), (10.0, 1e-2), (100.0, 1e-4), (1000.0, 1e-6), (20.0, 0.0)];
+        let binned = log_binned_spectrum(&spectrum, 3);
+        assert_eq!(binned.len(), 3);
+        assert_eq!(binned[0], (0.0, 0.0));
+        assert_eq!(binned[1], (1.0, -2.0));
+        assert!((binned[2].0 - 2.5).abs() < 1e-12);
+        assert!((binned[2].1 - ((1e-4 + 1e-6) / 2.0f64).log10()).abs() < 1e-12);
+        assert!(log_binned_spectrum(&spectrum, 0).is_empty());
+        assert!(log_binned_spectrum(&[(0.0, 1.0)], 4).is_empty());
+    }
+
+    #[test]
+    fn extreme_variance_slices_finds_busiest_and_flattest_planes() {
+        let mut lattice = Lattice::new((3, 3, 4)).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = 1.0 + match coord.z {
+                2 => coord.x as f64,
+                1 => 0.1 * coord.y as f64,
+                _ => 0.0,
+            };
+        }
+        let variances = slice_variances(&lattice);
+        assert_eq!(variances.len(), 4);
+        assert!(variances[2] > variances[1] && variances[1] > variances[0]);
+        assert_eq!(extreme_variance_slices(&lattice), Some((2, 0)));
+    }
+
+    #[test]
+    fn temperature_field_covers_every_cell() {
+        let mut lattice = Lattice::new((2, 1, 1)).unwrap();
+        assert_eq!(temperature_field(&lattice), vec![0.0, 0.0]);
+        lattice.at_mut(LatticeCoord { x: 1, y: 0, z: 0 }).unwrap().e = [[0.7; FORCES]; VARS];
+        let field = temperature_field(&lattice);
+        assert_eq!(field[0], 0.0);
+        assert!((field[1] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn z_slices_concatenate_to_the_density_field() {
+        let mut lattice = Lattice::new((3, 4, 5)).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[0][1] = (coord.x + 10 * coord.y + 100 * coord.z) as f64;
+        }
+        let planes: Vec<f64> = iter_slices(&lattice, 2).flatten().collect();
+        assert_eq!(planes, energy_density_field(&lattice));
+        let x_planes: Vec<Vec<f64>> = iter_slices(&lattice, 0).collect();
+        assert_eq!(x_planes.len(), 3);
+        assert_eq!(x_planes[2][..5], [2.0, 12.0, 22.0, 32.0, 102.0]);
+        assert_eq!(iter_slices(&lattice, 1).nth(1).unwrap()[..4], [10.0, 11.0, 12.0, 110.0]);
+        assert_eq!(iter_slices(&lattice, 3).count(), 0);
+    }
+
+    fn snapshot_path(name: &str) -> String {
+        std::env::temp_dir().join(format!("entropic_void_{}_{name}", std::process::id())).to_str().unwrap().to_string()
+    }
+
+    fn snapshot_lattice() -> Lattice {
+        let mut lattice = Lattice::new((3, 2, 2)).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[coord.x][coord.y] = 0.1 * (coord.x + coord.y + coord.z) as f64 + 1.0 / 3.0;
+        }
+        lattice
+    }
+
+    #[test]
+    fn snapshot_round_trips_exactly() {
+        let lattice = snapshot_lattice();
+        let path = snapshot_path("round_trip.csv");
+        export_full_snapshot(&lattice, 2.5, &path).unwrap();
+        let (time, back) = load_full_snapshot(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(time, 2.5);
+        assert_eq!(back.cells(), lattice.cells());
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn gzip_snapshot_round_trips_exactly() {
+        let lattice = snapshot_lattice();
+        let path = snapshot_path("round_trip.csv.gz");
+        export_full_snapshot(&lattice, 2.5, &path).unwrap();
+        let (time, back) = load_full_snapshot(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(time, 2.5);
+        assert_eq!(back.cells(), lattice.cells());
+    }
+
+    #[cfg(not(feature = "flate2"))]
+    #[test]
+    fn unsupported_gzip_export_leaves_existing_file_untouched() {
+        let lattice = snapshot_lattice();
+        let path = snapshot_path("untouched.csv.gz");
+        std::fs::write(&path, "keep me").unwrap();
+        let export = export_full_snapshot(&lattice, 1.0, &path).unwrap_err();
+        let append = append_full_snapshot(&lattice, 1.0, &path).unwrap_err();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(export.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(append.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(contents, "keep me");
+    }
+
+    #[test]
+    fn ramp_has_a_constant_interior_gradient() {
+        let mut lattice = Lattice::new((8, 5, 3)).unwrap();
+        let ramp = |c: LatticeCoord| 1.0 + 0.5 * c.x as f64 + 0.3 * c.y as f64;
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = ramp(coord);
+        }
+        let points = phase_space(&lattice);
+        assert_eq!(points.len(), lattice.cells().len());
+        for ((coord, _), &(rho, gradient)) in lattice.iter_cells().zip(&points) {
+            assert!((rho - ramp(coord)).abs() < 1e-12);
+            // Away from the periodic seam in x and y; z is flat everywhere.
+            if (1..7).contains(&coord.x) && (1..4).contains(&coord.y) {
+                assert!((gradient - (0.5f64.powi(2) + 0.3f64.powi(2)).sqrt()).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn rectangular_box_recovers_mode_k_and_total_power() {
+        let (sx, sy, sz) = (16, 32, 8);
+        let mut lattice = Lattice::new((sx, sy, sz)).unwrap();
+        for (c, cell) in lattice.iter_cells_mut() {
+            let phase = std::f64::consts::TAU
+                * (3.0 * c.x as f64 / sx as f64 + 5.0 * c.y as f64 / sy as f64 + c.z as f64 / sz as f64);
+            cell.e[0][0] = 2.0 * phase.cos();
+        }
+        let signal_power: f64 = lattice.cells().iter().map(|c| c.e[0][0].powi(2)).sum();
+        let power = utils::power_spectrum(&utils::fft_3d(&lattice, 0, 0));
+        let n = lattice.cells().len() as f64;
+        assert!((power.iter().sum::<f64>() / n - signal_power).abs() < 1e-8 * signal_power);
+
+        let modes = transport::compute_spatial_modes(&lattice, 0, 0);
+        assert_eq!(modes.iter().map(|m| m.k).collect::<Vec<_>>(), vec![(-3, -5, -1), (3, 5, 1)]);
+        let k = transport::fourier_mode_frequency((3, 5, 1), (sx, sy, sz));
+        let shell_width = std::f64::consts::TAU / sy as f64;
+        let occupied: Vec<_> = volume_fft(&lattice, 0, 0).into_iter().filter(|&(_, p)| p > 1e-9).collect();
+        assert_eq!(occupied.len(), 1);
+        assert!((occupied[0].0 - k).abs() <= shell_width / 2.0);
+    }
+
+    #[test]
+    fn flux_points_down_the_density_gradient() {
+        let mut lattice = Lattice::new((12, 3, 3)).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = 10.0 + 3.0 * (std::f64::consts::TAU * coord.x as f64 / 12.0).cos();
+        }
+        let flux = flux_field(&lattice, &[[0.2; FORCES]; VARS], 0.1);
+        // Density falls along +x at x = 3 and rises at x = 9.
+        let falling = flux[lattice.index(LatticeCoord { x: 3, y: 1, z: 1 }).unwrap() as usize];
+        assert!(falling.0 > 0.0 && falling.1.abs() < 1e-15 && falling.2.abs() < 1e-15);
+        let rising = flux[lattice.index(LatticeCoord { x: 9, y: 0, z: 2 }).unwrap() as usize];
+        assert!(rising.0 < 0.0);
+    }
+
+    #[test]
+    fn fit_slice_block_averages_each_axis_independently() {
+        let slice: Vec<Vec<f64>> = (0..10).map(|x| (0..4).map(|y| (x * 4 + y) as f64).collect()).collect();
+        let fitted = fit_slice(&slice, 4, 10).unwrap();
+        assert_eq!(fitted.factor, (3, 1));
+        assert_eq!((fitted.grid.len(), fitted.grid[0].len()), (4, 4));
+        assert_eq!(fitted.grid[0][0], (0.0 + 4.0 + 8.0) / 3.0);
+        // The last block along x holds only x = 9.
+        assert_eq!(fitted.grid[3][1], 37.0);
+        let fitted = fit_slice(&slice, 20, 20).unwrap();
+        assert_eq!(fitted.factor, (1, 1));
+        assert_eq!(fitted.grid, slice);
+        let fitted = fit_slice(&slice, 5, 2).unwrap();
+        assert_eq!(fitted.factor, (2, 2));
+        assert_eq!((fitted.grid.len(), fitted.grid[0].len()), (5, 2));
+        assert!(fit_slice(&slice, 0, 3).is_none());
+    }
+
+    #[test]
+    fn dominant_channel_is_the_largest_entry() {
+        let mut lattice = Lattice::new((3, 1, 1)).unwrap();
+        let hot = lattice.at_mut(LatticeCoord::default()).unwrap();
+        hot.e[3][2] = 2.0;
+        hot.e[1][1] = 1.0;
+        let tied = lattice.at_mut(LatticeCoord { x: 1, y: 0, z: 0 }).unwrap();
+        tied.e[4][3] = 1.0;
+        tied.e[2][0] = 1.0;
+        assert_eq!(dominant_channel_map(&lattice), vec![(3, 2), (2, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn uniform_projection_is_axis_length_times_cell_value() {
+        let mut lattice = Lattice::new((3, 4, 5)).unwrap();
+        for (_, cell) in lattice.iter_cells_mut() {
+            cell.e[1][2] = 0.5;
+            cell.e[0][0] = 1.0;
+        }
+        for (axis, var_i, shape, value) in
+            [(0, None, (4, 5), 3.0 * 1.5), (1, Some(1), (3, 5), 4.0 * 0.5), (2, None, (3, 4), 5.0 * 1.5)]
+        {
+            let map = projection(&lattice, axis, var_i);
+            assert_eq!((map.len(), map[0].len()), shape);
+            assert!(map.iter().flatten().all(|&v| v == value), "axis {axis}");
+        }
+        assert!(projection(&lattice, 3, None).is_empty());
+        assert!(projection(&lattice, 1, Some(VARS)).is_empty());
+    }
+
+    fn two_snapshot_series(name: &str) {
+        let path = snapshot_path(name);
+        let _ = std::fs::remove_file(&path);
+        let mut first = Lattice::new((2, 3, 1)).unwrap();
+        first.at_mut(LatticeCoord::default()).unwrap().e[1][1] = 0.25;
+        let mut second = Lattice::new((3, 1, 2)).unwrap();
+        second.at_mut(LatticeCoord::default()).unwrap().e[4][3] = 1.0 / 3.0;
+        append_full_snapshot(&first, 0.5, &path).unwrap();
+        append_full_snapshot(&second, 1.5, &path).unwrap();
+        let snapshots: io::Result<Vec<_>> = SnapshotReader::open(&path).unwrap().collect();
+        let first_only = load_full_snapshot(&path);
+        std::fs::remove_file(&path).unwrap();
+        let snapshots = snapshots.unwrap();
+        assert_eq!(snapshots.iter().map(|s| s.0).collect::<Vec<_>>(), vec![0.5, 1.5]);
+        assert_eq!(snapshots[0].1.cells(), first.cells());
+        assert_eq!(snapshots[1].1.cells(), second.cells());
+        assert_eq!(first_only.unwrap().0, 0.5);
+    }
+
+    #[test]
+    fn reader_walks_a_two_snapshot_file() {
+        two_snapshot_series("series.csv");
+    }
+
+    #[cfg(feature = "flate2")]
+    #[test]
+    fn reader_walks_a_two_member_gzip_file() {
+        two_snapshot_series("series.csv.gz");
+    }
+
+    #[test]
+    fn reader_stops_after_the_first_error() {
+        let mut reader = SnapshotReader::new(io::Cursor::new("# time=1 size=1,1,1\nh\n0,0,0,1\n"));
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+        assert!(SnapshotReader::new(io::Cursor::new("")).next().is_none());
+    }
+
+    #[test]
+    fn delta_field_starts_at_zero_and_sums_to_zero() {
+        let mut lattice = Lattice::new((3, 3, 3)).unwrap();
+        for (coord, cell) in lattice.iter_cells_mut() {
+            cell.e[0][0] = coord.x as f64;
+        }
+        let mut sim =
+            Simulation::new(lattice, RedistributionMatrix::default(), [[0.2; FORCES]; VARS], ConstraintSet::default())
+                .unwrap();
+        let delta = delta_field(&sim);
+        assert_eq!(delta.len(), 27);
+        assert!(delta.iter().all(|&v| v == 0.0));
+        sim.step(0.1).unwrap();
+        let delta = delta_field(&sim);
+        assert!(delta[0] > 0.0 && delta[2] < 0.0);
+        assert!(delta.iter().sum::<f64>().abs() < 1e-12);
+    }
+
+    #[test]
+    fn percentile_range_ignores_outliers_and_nan() {
+        let mut slice: Vec<Vec<f64>> = (0..10).map(|x| (0..10).map(|y| (x * 10 + y) as f64 / 99.0).collect()).collect();
+        slice[0][0] = -1e6;
+        slice[9][9] = 1e6;
+        slice[5][5] = f64::NAN;
+        let (low, high) = slice_percentile_range(&slice, 2.0, 98.0).unwrap();
+        assert!(low > 0.0 && low < 0.05 && high > 0.95 && high < 1.0, "{low} {high}");
+        let (min, max, _) = slice_stats(&slice).unwrap();
+        assert_eq!(slice_percentile_range(&slice, 0.0, 100.0), Some((min, max)));
+        assert_eq!(slice_percentile_range(&slice, 60.0, 50.0), None);
+        assert_eq!(slice_percentile_range(&slice, -1.0, 50.0), None);
+        assert_eq!(slice_percentile_range(&[vec![3.0]], 2.0, 98.0), Some((3.0, 3.0)));
+        assert_eq!(slice_percentile_range(&[vec![f64::NAN]], 2.0, 98.0), None);
+        assert_eq!(slice_percentile_range(&[], 2.0, 98.0), None);
+    }
+}
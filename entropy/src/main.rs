@@ -3,6 +3,7 @@
 use entropic_void::{
     generate_lattice,
     simulate_tick,
+    TensorConfig,
 };
 
 fn main() {
@@ -12,7 +13,8 @@ fn main() {
     let total_energy = 1000.0;
 
     // Initialize lattice
-    let mut lattice = generate_lattice(seed, lattice_size, total_energy);
+    let mut lattice = generate_lattice(seed, lattice_size, total_energy)
+        .expect("lattice size must be non-zero");
     println!("=== Initial Lattice ===");
     for (i, cell) in lattice.cells.iter().enumerate() {
         println!("Cell {}: total_energy = {:.3}", i, cell.total_energy);
@@ -22,7 +24,7 @@ fn main() {
     }
 
     // Run one simulation tick
-    simulate_tick(&mut lattice, seed);
+    simulate_tick(&mut lattice, seed, &TensorConfig::default());
 
     // Print updated lattice
     println!("\n=== Lattice After 1 Tick ===");